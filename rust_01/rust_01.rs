@@ -53,7 +53,7 @@ fn main() {
     }
 
     let mut sorted: Vec<_> = freq.into_iter().collect();
-    sorted.sort_by(|a, b| b.1.cmp(&a.1));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1));
 
     for (word, count) in sorted.into_iter().take(args.top) {
         println!("{}: {}", word, count);
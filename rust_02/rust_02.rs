@@ -1,8 +1,10 @@
-use clap::Parser;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clap::{builder::PossibleValuesParser, builder::TypedValueParser, Parser, ValueEnum};
+use sha2::{Digest, Sha256};
 use std::{
-    fs::OpenOptions,
+    fs::{File, OpenOptions},
     io::{self, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 /// Read and write binary files in hexadecimal
@@ -18,16 +20,334 @@ struct Args {
     read_mode: bool,
 
     /// Write mode (hex string to write)
-    #[arg(short = 'w', long = "write", group = "mode")]
+    #[arg(short = 'w', long = "write", groups = ["mode", "write"])]
     write_hex_string: Option<String>,
 
-    /// Offset in bytes (decimal or 0x hex)
-    #[arg(short = 'o', long = "offset", default_value = "0", value_parser = parse_offset)]
+    /// Write mode (ASCII/UTF-8 string to write as raw bytes)
+    #[arg(long = "write-ascii", groups = ["mode", "write"])]
+    write_ascii: Option<String>,
+
+    /// Write mode (base64 string decoded and written as raw bytes)
+    #[arg(long = "base64-decode", groups = ["mode", "write"], value_name = "B64")]
+    base64_decode: Option<String>,
+
+    /// Base64-encode the region at --offset/--size (or --all) to stdout
+    #[arg(long = "base64-encode", group = "mode")]
+    base64_encode: bool,
+
+    /// Write mode (hex string read from a file, whitespace ignored)
+    #[arg(long = "write-from-hex-file", groups = ["mode", "write"], value_name = "PATH")]
+    write_hex_file: Option<PathBuf>,
+
+    /// Write at the end of the file instead of --offset
+    #[arg(long = "append", requires = "write")]
+    append: bool,
+
+    /// Search mode (hex byte pattern to find)
+    #[arg(long = "search", group = "mode")]
+    search_hex: Option<String>,
+
+    /// Count mode (number of occurrences of a hex byte pattern)
+    #[arg(long = "count", group = "mode")]
+    count_hex: Option<String>,
+
+    /// Replace mode (FIND_HEX:REPLACE_HEX, equal lengths)
+    #[arg(long = "replace", group = "mode")]
+    replace_spec: Option<String>,
+
+    /// Fill mode (hex pattern repeated over --length bytes)
+    #[arg(long = "fill", group = "mode", requires = "length")]
+    fill_hex: Option<String>,
+
+    /// Number of bytes to fill
+    #[arg(long = "length")]
+    length: Option<u64>,
+
+    /// Diff mode (compare the target byte-by-byte against another file)
+    #[arg(long = "diff", group = "mode")]
+    diff_file: Option<PathBuf>,
+
+    /// Checksum mode (hash the region at --offset/--size, or --all)
+    #[arg(long = "checksum", group = "mode", value_enum)]
+    checksum_algo: Option<ChecksumAlgo>,
+
+    /// With --checksum: expected checksum (hex), errors on mismatch.
+    /// With a write mode: read the bytes back and confirm they landed.
+    #[arg(long = "verify", num_args = 0..=1, value_name = "HEX")]
+    verify: Option<Option<String>>,
+
+    /// Revert mode (rebuild the target file from a hex dump text file)
+    #[arg(long = "revert", group = "mode")]
+    revert_dump: Option<PathBuf>,
+
+    /// Print a byte-value histogram of the region at --offset/--size (or --all)
+    #[arg(long = "histogram", conflicts_with = "mode")]
+    histogram: bool,
+
+    /// Print the Shannon entropy (bits/byte) of the region
+    #[arg(long = "entropy", conflicts_with = "mode")]
+    entropy: bool,
+
+    /// Strings mode (print printable ASCII runs of at least MINLEN bytes)
+    #[arg(long = "strings", group = "mode", value_name = "MINLEN")]
+    strings_min_len: Option<usize>,
+
+    /// XOR mode (XOR the region at --offset/--size, or --all, with a repeating hex key)
+    #[arg(long = "xor", group = "mode", value_name = "HEX_KEY")]
+    xor_key: Option<String>,
+
+    /// Truncate mode (set the file length to N bytes, zero-extending if larger)
+    #[arg(long = "truncate", group = "mode", value_name = "N", value_parser = parse_offset)]
+    truncate_len: Option<u64>,
+
+    /// Tail mode (dump the last N bytes of the file)
+    #[arg(long = "tail", group = "mode", value_name = "N", value_parser = parse_offset)]
+    tail_len: Option<u64>,
+
+    /// Copy mode (copy the region at --offset/--size into DEST at --dest-offset)
+    #[arg(long = "copy-to", group = "mode", value_name = "DEST")]
+    copy_dest: Option<PathBuf>,
+
+    /// Offset in the destination file for --copy-to
+    #[arg(long = "dest-offset", default_value = "0", value_parser = parse_offset)]
+    dest_offset: u64,
+
+    /// Patch mode (write the whole contents of SRC at --offset)
+    #[arg(long = "patch-from", group = "mode", value_name = "SRC")]
+    patch_src: Option<PathBuf>,
+
+    /// Swap mode (reverse byte order within each WIDTH-byte word of the region)
+    #[arg(
+        long = "swap",
+        group = "mode",
+        value_name = "WIDTH",
+        value_parser = PossibleValuesParser::new(["2", "4", "8"])
+            .map(|s| s.parse::<usize>().unwrap())
+    )]
+    swap_width: Option<usize>,
+
+    /// Redact mode (zero --size bytes at --offset, dry run unless --yes)
+    #[arg(long = "redact", group = "mode")]
+    redact: bool,
+
+    /// Confirm a destructive --redact
+    #[arg(long = "yes", requires = "redact")]
+    yes: bool,
+
+    /// Offset in bytes (decimal or 0x hex; -N or end-N counts from EOF)
+    #[arg(
+        short = 'o',
+        long = "offset",
+        default_value = "0",
+        value_parser = parse_offset_spec,
+        allow_hyphen_values = true
+    )]
+    offset_spec: OffsetSpec,
+
+    /// Absolute offset, resolved from `offset_spec` once the file is known
+    #[arg(skip)]
     offset: u64,
 
     /// Number of bytes to read
     #[arg(short = 's', long = "size", default_value = "16")]
     size: usize,
+
+    /// Output format for read mode
+    #[arg(
+        long = "format",
+        value_enum,
+        default_value = "hex",
+        conflicts_with = "all"
+    )]
+    format: OutputFormat,
+
+    /// Also interpret the read bytes as integers of this type
+    #[arg(long = "as", value_enum, conflicts_with = "all")]
+    as_type: Option<IntType>,
+
+    /// Byte order used by --as
+    #[arg(long = "endian", value_enum, default_value = "little")]
+    endian: Endian,
+
+    /// Bytes per line in hex dumps and --format source output
+    #[arg(long = "width", default_value = "16", value_parser = parse_width)]
+    width: usize,
+
+    /// Base of the offset column in hex dumps (also used to parse --revert input)
+    #[arg(long = "offset-format", value_enum, default_value = "hex")]
+    offset_format: OffsetFormat,
+
+    /// Color hex dump bytes by category (null, printable, control, high)
+    #[arg(long = "color")]
+    color: bool,
+
+    /// Read several regions instead of --offset/--size (e.g. 0x10:16,0x40:8)
+    #[arg(
+        long = "regions",
+        value_delimiter = ',',
+        value_parser = parse_region,
+        requires = "read_mode",
+        conflicts_with = "all"
+    )]
+    regions: Vec<Region>,
+
+    /// Show a progress bar on stderr when dumping, checksumming or searching
+    #[arg(long = "progress")]
+    progress: bool,
+
+    /// Read from the offset to the end of the file (ignores --size)
+    #[arg(long = "all")]
+    all: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ChecksumAlgo {
+    Crc32,
+    Sha256,
+}
+
+impl ChecksumAlgo {
+    fn label(self) -> &'static str {
+        match self {
+            ChecksumAlgo::Crc32 => "CRC32",
+            ChecksumAlgo::Sha256 => "SHA-256",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum IntType {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+}
+
+impl IntType {
+    fn width(self) -> usize {
+        match self {
+            IntType::U8 | IntType::I8 => 1,
+            IntType::U16 | IntType::I16 => 2,
+            IntType::U32 | IntType::I32 => 4,
+            IntType::U64 | IntType::I64 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Endian {
+    Big,
+    Little,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Hex dump with offsets and an ASCII column
+    Hex,
+    /// C initializer: `unsigned char data[] = { 0x3f, ... };`
+    CArray,
+    /// Python bytes literal: `b'\x3f...'`
+    PythonBytes,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OffsetFormat {
+    Hex,
+    Dec,
+}
+
+impl OffsetFormat {
+    fn format(self, offset: u64) -> String {
+        match self {
+            OffsetFormat::Hex => format!("{:08x}", offset),
+            OffsetFormat::Dec => format!("{:010}", offset),
+        }
+    }
+
+    fn radix(self) -> u32 {
+        match self {
+            OffsetFormat::Hex => 16,
+            OffsetFormat::Dec => 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum OffsetSpec {
+    Start(u64),
+    End(u64),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Region {
+    offset: u64,
+    size: usize,
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const PROGRESS_BAR_WIDTH: u64 = 20;
+
+/// Wraps a reader and reports on stderr how far into the file it has read,
+/// so stdout stays clean for redirection.
+struct ProgressReader<R> {
+    inner: R,
+    position: u64,
+    total: u64,
+    enabled: bool,
+    last_percent: Option<u64>,
+}
+
+impl<R> ProgressReader<R> {
+    fn new(inner: R, start: u64, total: u64, enabled: bool) -> Self {
+        Self {
+            inner,
+            position: start,
+            total,
+            enabled,
+            last_percent: None,
+        }
+    }
+
+    fn report(&mut self) {
+        if !self.enabled || self.total == 0 {
+            return;
+        }
+        let percent = self.position.min(self.total) * 100 / self.total;
+        if self.last_percent == Some(percent) {
+            return;
+        }
+        self.last_percent = Some(percent);
+
+        let filled = (percent * PROGRESS_BAR_WIDTH / 100) as usize;
+        let empty = PROGRESS_BAR_WIDTH as usize - filled;
+        eprint!(
+            "\r[{}{}] {:>3}%",
+            "#".repeat(filled),
+            " ".repeat(empty),
+            percent
+        );
+    }
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.position += bytes_read as u64;
+        self.report();
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Drop for ProgressReader<R> {
+    fn drop(&mut self) {
+        if self.last_percent.is_some() {
+            eprintln!();
+        }
+    }
 }
 
 fn parse_offset(s: &str) -> Result<u64, String> {
@@ -39,49 +359,396 @@ fn parse_offset(s: &str) -> Result<u64, String> {
     }
 }
 
+fn parse_offset_spec(s: &str) -> Result<OffsetSpec, String> {
+    if s == "end" {
+        Ok(OffsetSpec::End(0))
+    } else if let Some(back) = s.strip_prefix("end-").or_else(|| s.strip_prefix('-')) {
+        parse_offset(back).map(OffsetSpec::End)
+    } else {
+        parse_offset(s).map(OffsetSpec::Start)
+    }
+}
+
+/// Turn an end-relative offset into an absolute one by seeking from EOF.
+fn resolve_offset(path: &Path, spec: OffsetSpec) -> Result<u64, String> {
+    let back = match spec {
+        OffsetSpec::Start(offset) => return Ok(offset),
+        OffsetSpec::End(back) => back,
+    };
+
+    let mut file = File::open(path).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| format!("Erreur I/O: {}", e))?
+        .len();
+    if back > len {
+        return Err(format!(
+            "Erreur: L'offset end-{} dépasse le début du fichier ({} octets).",
+            back, len
+        ));
+    }
+    file.seek(SeekFrom::End(-(back as i64)))
+        .map_err(|e| format!("Erreur I/O seek: {}", e))
+}
+
+fn parse_region(s: &str) -> Result<Region, String> {
+    let (offset, size) = s
+        .split_once(':')
+        .ok_or_else(|| format!("Région invalide {:?}, format attendu OFFSET:SIZE", s))?;
+    Ok(Region {
+        offset: parse_offset(offset)?,
+        size: size
+            .parse()
+            .map_err(|e| format!("Taille de région invalide: {}", e))?,
+    })
+}
+
+fn parse_width(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(0) => Err(String::from("La largeur doit être supérieure à 0")),
+        Ok(width) => Ok(width),
+        Err(e) => Err(format!("Largeur invalide: {}", e)),
+    }
+}
+
+fn decode_hex(hex_string: &str) -> Result<Vec<u8>, String> {
+    hex::decode(hex_string).map_err(|_| String::from("Erreur: Chaîne hexadécimale invalide."))
+}
+
+/// Read a hex payload from `path`, dropping whitespace and newlines. Decode
+/// errors quote the surrounding characters so the bad spot is easy to find.
+fn read_hex_file(path: &Path) -> Result<Vec<u8>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let hex_string: String = content.chars().filter(|c| !c.is_whitespace()).collect();
+
+    hex::decode(&hex_string).map_err(|e| match e {
+        hex::FromHexError::InvalidHexCharacter { c, index } => {
+            let start = hex_string[..index]
+                .char_indices()
+                .rev()
+                .nth(7)
+                .map_or(0, |(i, _)| i);
+            let context: String = hex_string[start..].chars().take(17).collect();
+            format!(
+                "Erreur: Caractère hexadécimal invalide {:?} à la position {} (près de {:?}).",
+                c, index, context
+            )
+        }
+        hex::FromHexError::OddLength => format!(
+            "Erreur: Nombre impair de chiffres hexadécimaux ({}).",
+            hex_string.len()
+        ),
+        other => format!("Erreur: Chaîne hexadécimale invalide ({}).", other),
+    })
+}
+
+fn is_printable(b: u8) -> bool {
+    (0x20..=0x7E).contains(&b)
+}
+
 fn format_ascii(b: u8) -> char {
-    if (0x20..=0x7E).contains(&b) {
+    if is_printable(b) {
         b as char
     } else {
         '.'
     }
 }
 
-fn print_hex_dump(buffer: &[u8], base_offset: u64) {
+/// Layout options shared by every hex dump.
+#[derive(Clone, Copy)]
+struct DumpOptions {
+    width: usize,
+    color: bool,
+    offset_format: OffsetFormat,
+}
+
+impl DumpOptions {
+    fn from_args(args: &Args) -> Self {
+        Self {
+            width: args.width,
+            color: args.color,
+            offset_format: args.offset_format,
+        }
+    }
+}
+
+fn byte_color(b: u8) -> &'static str {
+    match b {
+        0x00 => "2",         // Dim
+        0x20..=0x7E => "32", // Green
+        0x80..=0xFF => "33", // Yellow
+        _ => "31",           // Red (control bytes)
+    }
+}
+
+fn paint(text: &str, b: u8, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", byte_color(b), text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn print_hex_dump(buffer: &[u8], base_offset: u64, opts: DumpOptions) {
+    // Keep the extra gap every 8 bytes only when rows split evenly into groups.
+    let grouped = opts.width.is_multiple_of(8);
+    let gaps = if grouped { opts.width / 8 - 1 } else { 0 };
+    let hex_width = opts.width * 3 + gaps;
+
     let mut offset = base_offset;
-    for chunk in buffer.chunks(16) {
-        let hex_part: String = chunk
+    for chunk in buffer.chunks(opts.width) {
+        // Pad on the visible length so escape codes don't break alignment.
+        let mut hex_part = String::new();
+        let mut visible = 0;
+        for (i, &b) in chunk.iter().enumerate() {
+            if i > 0 {
+                let sep = if grouped && i % 8 == 0 { "  " } else { " " };
+                hex_part.push_str(sep);
+                visible += sep.len();
+            }
+            hex_part.push_str(&paint(&format!("{:02x}", b), b, opts.color));
+            visible += 2;
+        }
+        let padding = " ".repeat(hex_width - visible);
+        let ascii_part: String = chunk
             .iter()
-            .enumerate()
-            .map(|(i, &b)| format!("{:02x}{}", b, if i == 7 { "  " } else { " " }))
+            .map(|&b| paint(&format_ascii(b).to_string(), b, opts.color))
             .collect();
-        let ascii_part: String = chunk.iter().map(|&b| format_ascii(b)).collect();
 
-        let padded_hex = format!("{:<49}", hex_part.trim_end());
-        println!("{:08x}: {}|{}|", offset, padded_hex, ascii_part);
+        println!(
+            "{}: {}{}|{}|",
+            opts.offset_format.format(offset),
+            hex_part,
+            padding,
+            ascii_part
+        );
         offset += chunk.len() as u64;
     }
 }
 
+fn print_c_array(buffer: &[u8], per_line: usize) {
+    println!("unsigned char data[] = {{");
+    for chunk in buffer.chunks(per_line) {
+        let line: Vec<String> = chunk.iter().map(|b| format!("0x{:02x}", b)).collect();
+        println!("    {},", line.join(", "));
+    }
+    println!("}};");
+    println!("unsigned int data_len = {};", buffer.len());
+}
+
+fn print_python_bytes(buffer: &[u8], per_line: usize) {
+    let literal = |chunk: &[u8]| -> String {
+        let escaped: String = chunk.iter().map(|b| format!("\\x{:02x}", b)).collect();
+        format!("b'{}'", escaped)
+    };
+
+    if buffer.len() <= per_line {
+        println!("data = {}", literal(buffer));
+        return;
+    }
+    println!("data = (");
+    for chunk in buffer.chunks(per_line) {
+        println!("    {}", literal(chunk));
+    }
+    println!(")");
+}
+
+fn decode_int(bytes: &[u8], ty: IntType, endian: Endian) -> String {
+    macro_rules! decode {
+        ($t:ty) => {{
+            let raw = bytes.try_into().expect("slice length matches type width");
+            match endian {
+                Endian::Big => <$t>::from_be_bytes(raw).to_string(),
+                Endian::Little => <$t>::from_le_bytes(raw).to_string(),
+            }
+        }};
+    }
+
+    match ty {
+        IntType::U8 => decode!(u8),
+        IntType::I8 => decode!(i8),
+        IntType::U16 => decode!(u16),
+        IntType::I16 => decode!(i16),
+        IntType::U32 => decode!(u32),
+        IntType::I32 => decode!(i32),
+        IntType::U64 => decode!(u64),
+        IntType::I64 => decode!(i64),
+    }
+}
+
+fn print_integers(buffer: &[u8], base_offset: u64, ty: IntType, endian: Endian) {
+    let label = format!("{:?} ({:?} endian)", ty, endian).to_lowercase();
+    println!("\nAs {}:", label);
+    let chunks = buffer.chunks_exact(ty.width());
+    let rest = chunks.remainder();
+    for (i, chunk) in chunks.enumerate() {
+        let offset = base_offset + (i * ty.width()) as u64;
+        println!("{:08x}: {}", offset, decode_int(chunk, ty, endian));
+    }
+    if !rest.is_empty() {
+        let offset = base_offset + (buffer.len() - rest.len()) as u64;
+        let bytes: Vec<String> = rest.iter().map(|b| format!("{:02x}", b)).collect();
+        println!(
+            "{:08x}: {} (trailing {} byte(s), too short to decode)",
+            offset,
+            bytes.join(" "),
+            rest.len()
+        );
+    }
+}
+
+/// Read until `buffer` is full or EOF is reached, returning the byte count.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
 fn handle_read(args: &Args) -> io::Result<()> {
+    if args.regions.is_empty() {
+        return read_region(args, args.offset, args.size);
+    }
+
+    for (i, region) in args.regions.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "Region {}: offset 0x{:08x}, {} bytes",
+            i + 1,
+            region.offset,
+            region.size
+        );
+        read_region(args, region.offset, region.size)?;
+    }
+    Ok(())
+}
+
+fn read_region(args: &Args, offset: u64, size: usize) -> io::Result<()> {
     let mut file = OpenOptions::new().read(true).open(&args.target_file)?;
-    file.seek(SeekFrom::Start(args.offset))?;
-    let mut buffer = vec![0u8; args.size];
+    file.seek(SeekFrom::Start(offset))?;
+
+    if args.all {
+        let total = file.metadata()?.len();
+        let mut reader = ProgressReader::new(file, offset, total, args.progress);
+        return dump_to_eof(&mut reader, offset, DumpOptions::from_args(args));
+    }
+
+    if let Some(ty) = args.as_type {
+        if !size.is_multiple_of(ty.width()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "--size ({}) doit être un multiple de {} pour ce type",
+                    size,
+                    ty.width()
+                ),
+            ));
+        }
+    }
+
+    let mut buffer = vec![0u8; size];
     let bytes_read = file.read(&mut buffer)?;
     buffer.truncate(bytes_read);
 
     if bytes_read > 0 {
-        print_hex_dump(&buffer, args.offset);
+        match args.format {
+            OutputFormat::Hex => print_hex_dump(&buffer, offset, DumpOptions::from_args(args)),
+            OutputFormat::CArray => print_c_array(&buffer, args.width),
+            OutputFormat::PythonBytes => print_python_bytes(&buffer, args.width),
+        }
+        if let Some(ty) = args.as_type {
+            print_integers(&buffer, offset, ty, args.endian);
+        }
     } else {
-        println!("Aucun octet lu à l'offset 0x{:x}.", args.offset);
+        println!("Aucun octet lu à l'offset 0x{:x}.", offset);
+    }
+    Ok(())
+}
+
+fn handle_tail(args: &Args, count: u64) -> io::Result<()> {
+    let mut file = File::open(&args.target_file)?;
+    let len = file.metadata()?.len();
+    // Files shorter than the request are dumped whole.
+    let count = count.min(len);
+    let start = file.seek(SeekFrom::End(-(count as i64)))?;
+    let mut reader = ProgressReader::new(file, start, len, args.progress);
+    dump_to_eof(&mut reader, start, DumpOptions::from_args(args))
+}
+
+fn file_len(file: &File) -> Result<u64, String> {
+    file.metadata()
+        .map(|m| m.len())
+        .map_err(|e| format!("Erreur I/O: {}", e))
+}
+
+/// Open the target file positioned at `--offset`, limited to `--size` bytes
+/// unless `--all` was given.
+fn open_region(args: &Args) -> io::Result<io::Take<File>> {
+    let mut file = File::open(&args.target_file)?;
+    file.seek(SeekFrom::Start(args.offset))?;
+    let limit = if args.all { u64::MAX } else { args.size as u64 };
+    Ok(file.take(limit))
+}
+
+fn dump_to_eof<R: Read>(reader: &mut R, start: u64, opts: DumpOptions) -> io::Result<()> {
+    // Whole rows per chunk so line offsets stay aligned across chunks.
+    let mut buffer = vec![0u8; (CHUNK_SIZE / opts.width).max(1) * opts.width];
+    let mut offset = start;
+
+    loop {
+        let bytes_read = read_full(reader, &mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        print_hex_dump(&buffer[..bytes_read], offset, opts);
+        offset += bytes_read as u64;
+    }
+
+    if offset == start {
+        println!("Aucun octet lu à l'offset 0x{:x}.", start);
+    }
+    Ok(())
+}
+
+/// Position `file` at `offset` for writing. When `offset` lies past EOF the
+/// gap is written out as explicit zeros instead of leaving a sparse hole.
+fn seek_for_write(file: &mut File, offset: u64) -> Result<(), String> {
+    let len = file
+        .metadata()
+        .map_err(|e| format!("Erreur I/O: {}", e))?
+        .len();
+
+    if offset > len {
+        file.seek(SeekFrom::Start(len))
+            .map_err(|e| format!("Erreur I/O seek: {}", e))?;
+        let zeros = vec![0u8; CHUNK_SIZE];
+        let mut remaining = offset - len;
+        while remaining > 0 {
+            let n = remaining.min(CHUNK_SIZE as u64) as usize;
+            file.write_all(&zeros[..n])
+                .map_err(|e| format!("Erreur I/O write: {}", e))?;
+            remaining -= n as u64;
+        }
     }
+
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("Erreur I/O seek: {}", e))?;
     Ok(())
 }
 
-fn handle_write(args: &Args, hex_string: &str) -> Result<(), String> {
-    let bytes_to_write = hex::decode(hex_string)
-        .map_err(|_| String::from("Erreur: Chaîne hexadécimale invalide."))?;
+fn write_at(file: &mut File, offset: u64, bytes: &[u8]) -> Result<(), String> {
+    seek_for_write(file, offset)?;
+    file.write_all(bytes)
+        .map_err(|e| format!("Erreur I/O write: {}", e))
+}
 
+fn handle_write(args: &Args, bytes_to_write: &[u8]) -> Result<(), String> {
     let write_len = bytes_to_write.len();
 
     let mut file = OpenOptions::new()
@@ -92,17 +759,18 @@ fn handle_write(args: &Args, hex_string: &str) -> Result<(), String> {
         .open(&args.target_file)
         .map_err(|e| format!("Erreur I/O: {}", e))?;
 
-    file.seek(SeekFrom::Start(args.offset))
-        .map_err(|e| format!("Erreur I/O seek: {}", e))?;
-    file.write_all(&bytes_to_write)
-        .map_err(|e| format!("Erreur I/O write: {}", e))?;
+    let offset = if args.append {
+        file.seek(SeekFrom::End(0))
+            .map_err(|e| format!("Erreur I/O seek: {}", e))?
+    } else {
+        args.offset
+    };
+
+    write_at(&mut file, offset, bytes_to_write)?;
     file.flush()
         .map_err(|e| format!("Erreur I/O flush: {}", e))?;
 
-    println!(
-        "Writing {} bytes at offset 0x{:08x}",
-        write_len, args.offset
-    );
+    println!("Writing {} bytes at offset 0x{:08x}", write_len, offset);
 
     let hex_formatted: Vec<String> = bytes_to_write
         .iter()
@@ -115,19 +783,840 @@ fn handle_write(args: &Args, hex_string: &str) -> Result<(), String> {
 
     println!("✓ Successfully written");
 
+    if args.append {
+        println!("File size: {} bytes", offset + write_len as u64);
+    }
+
+    if args.verify.is_some() {
+        verify_written(&mut file, offset, bytes_to_write)?;
+    }
+
+    Ok(())
+}
+
+/// Seek back to `offset`, re-read what was just written and compare.
+fn verify_written(file: &mut File, offset: u64, expected: &[u8]) -> Result<(), String> {
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("Erreur I/O seek: {}", e))?;
+    let mut readback = vec![0u8; expected.len()];
+    let bytes_read =
+        read_full(file, &mut readback).map_err(|e| format!("Erreur I/O read: {}", e))?;
+
+    if bytes_read != expected.len() {
+        return Err(format!(
+            "Erreur: Vérification échouée, {} octets relus sur {}.",
+            bytes_read,
+            expected.len()
+        ));
+    }
+    if let Some(i) = readback.iter().zip(expected).position(|(a, b)| a != b) {
+        return Err(format!(
+            "Erreur: Vérification échouée à l'offset 0x{:08x} ({:02x} != {:02x}).",
+            offset + i as u64,
+            readback[i],
+            expected[i]
+        ));
+    }
+
+    println!("✓ Verified {} bytes", expected.len());
+    Ok(())
+}
+
+/// Scan the reader chunk by chunk and call `on_match` with the offset of
+/// every occurrence of `pattern`, keeping `pattern.len() - 1` bytes between
+/// chunks so matches spanning a chunk boundary are still found.
+fn scan_pattern<R: Read, F: FnMut(u64)>(
+    reader: &mut R,
+    pattern: &[u8],
+    mut on_match: F,
+) -> io::Result<()> {
+    let mut window: Vec<u8> = Vec::with_capacity(CHUNK_SIZE + pattern.len());
+    let mut window_start = 0u64;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let bytes_read = reader.read(&mut chunk)?;
+        if bytes_read == 0 {
+            break;
+        }
+        window.extend_from_slice(&chunk[..bytes_read]);
+        if window.len() < pattern.len() {
+            continue;
+        }
+
+        for (i, candidate) in window.windows(pattern.len()).enumerate() {
+            if candidate == pattern {
+                on_match(window_start + i as u64);
+            }
+        }
+
+        let consumed = window.len() - (pattern.len() - 1);
+        window.drain(..consumed);
+        window_start += consumed as u64;
+    }
+
+    Ok(())
+}
+
+fn find_pattern<R: Read>(reader: &mut R, pattern: &[u8]) -> io::Result<Vec<u64>> {
+    let mut matches = Vec::new();
+    scan_pattern(reader, pattern, |offset| matches.push(offset))?;
+    Ok(matches)
+}
+
+fn handle_search(args: &Args, hex_string: &str) -> Result<(), String> {
+    let pattern = decode_hex(hex_string)?;
+    if pattern.is_empty() {
+        return Err(String::from("Erreur: Motif de recherche vide."));
+    }
+
+    let file = File::open(&args.target_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let total = file_len(&file)?;
+    let mut file = ProgressReader::new(file, 0, total, args.progress);
+    let matches =
+        find_pattern(&mut file, &pattern).map_err(|e| format!("Erreur I/O read: {}", e))?;
+    drop(file);
+
+    if matches.is_empty() {
+        println!("No matches found for {}", hex::encode(&pattern));
+    } else {
+        for offset in &matches {
+            println!("0x{:08x}", offset);
+        }
+        println!("Found {} match(es)", matches.len());
+    }
+
+    Ok(())
+}
+
+fn handle_count(args: &Args, hex_string: &str) -> Result<(), String> {
+    let pattern = decode_hex(hex_string)?;
+    if pattern.is_empty() {
+        return Err(String::from("Erreur: Motif de recherche vide."));
+    }
+
+    let file = File::open(&args.target_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let total = file_len(&file)?;
+    let mut file = ProgressReader::new(file, 0, total, args.progress);
+    let mut count = 0u64;
+    scan_pattern(&mut file, &pattern, |_| count += 1)
+        .map_err(|e| format!("Erreur I/O read: {}", e))?;
+    drop(file);
+
+    println!("{}", count);
+    Ok(())
+}
+
+fn handle_replace(args: &Args, spec: &str) -> Result<(), String> {
+    let (find_hex, replace_hex) = spec.split_once(':').ok_or_else(|| {
+        String::from("Erreur: Format attendu pour --replace: FIND_HEX:REPLACE_HEX.")
+    })?;
+    let find = decode_hex(find_hex)?;
+    let replacement = decode_hex(replace_hex)?;
+    if find.is_empty() {
+        return Err(String::from("Erreur: Motif de recherche vide."));
+    }
+    if find.len() != replacement.len() {
+        return Err(format!(
+            "Erreur: Les motifs doivent avoir la même longueur ({} != {} octets).",
+            find.len(),
+            replacement.len()
+        ));
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    let matches = find_pattern(&mut file, &find).map_err(|e| format!("Erreur I/O read: {}", e))?;
+
+    // Overlapping matches would clobber each other, keep the first of each run.
+    let mut replaced = Vec::new();
+    for offset in matches {
+        if replaced
+            .last()
+            .is_some_and(|&last: &u64| offset < last + find.len() as u64)
+        {
+            continue;
+        }
+        write_at(&mut file, offset, &replacement)?;
+        replaced.push(offset);
+    }
+    file.flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+
+    if replaced.is_empty() {
+        println!("No matches found for {}", hex::encode(&find));
+    } else {
+        for offset in &replaced {
+            println!(
+                "0x{:08x}: {} -> {}",
+                offset,
+                hex::encode(&find),
+                hex::encode(&replacement)
+            );
+        }
+        println!("✓ Replaced {} occurrence(s)", replaced.len());
+    }
+
+    Ok(())
+}
+
+/// Write `pattern` repeatedly from `offset` until `length` bytes are written,
+/// one chunk at a time so large fills never allocate the whole region.
+fn fill_region(file: &mut File, offset: u64, pattern: &[u8], length: u64) -> Result<(), String> {
+    seek_for_write(file, offset)?;
+
+    // Whole repetitions of the pattern so every chunk starts in phase.
+    let repeats = (CHUNK_SIZE / pattern.len()).max(1);
+    let chunk = pattern.repeat(repeats);
+
+    let mut remaining = length;
+    while remaining > 0 {
+        let n = remaining.min(chunk.len() as u64) as usize;
+        file.write_all(&chunk[..n])
+            .map_err(|e| format!("Erreur I/O write: {}", e))?;
+        remaining -= n as u64;
+    }
+    file.flush().map_err(|e| format!("Erreur I/O flush: {}", e))
+}
+
+fn handle_fill(args: &Args, hex_string: &str) -> Result<(), String> {
+    let pattern = decode_hex(hex_string)?;
+    if pattern.is_empty() {
+        return Err(String::from("Erreur: Motif de remplissage vide."));
+    }
+    let length = args.length.unwrap_or(0);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    fill_region(&mut file, args.offset, &pattern, length)?;
+
+    println!(
+        "Filling {} bytes at offset 0x{:08x} with pattern {}",
+        length,
+        args.offset,
+        hex::encode(&pattern)
+    );
+    println!("✓ Successfully written {} bytes", length);
+
+    Ok(())
+}
+
+fn handle_redact(args: &Args) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    // Redaction scrubs existing bytes only, it never grows the file.
+    let len = file_len(&file)?;
+    let length = (args.size as u64).min(len.saturating_sub(args.offset));
+
+    if !args.yes {
+        println!(
+            "Would redact {} bytes at offset 0x{:08x} (0x{:08x}..0x{:08x})",
+            length,
+            args.offset,
+            args.offset,
+            args.offset + length
+        );
+        println!("Nothing written, re-run with --yes to confirm");
+        return Ok(());
+    }
+
+    fill_region(&mut file, args.offset, &[0x00], length)?;
+
+    println!("Redacting {} bytes at offset 0x{:08x}", length, args.offset);
+    println!("✓ Successfully zeroed {} bytes", length);
+
+    Ok(())
+}
+
+fn handle_diff(args: &Args, other_file: &Path) -> Result<(), String> {
+    let mut left = File::open(&args.target_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let mut right = File::open(other_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+
+    let mut left_buf = vec![0u8; CHUNK_SIZE];
+    let mut right_buf = vec![0u8; CHUNK_SIZE];
+    let mut offset = 0u64;
+    let mut differences = 0u64;
+
+    loop {
+        let left_len =
+            read_full(&mut left, &mut left_buf).map_err(|e| format!("Erreur I/O read: {}", e))?;
+        let right_len =
+            read_full(&mut right, &mut right_buf).map_err(|e| format!("Erreur I/O read: {}", e))?;
+
+        let common = left_len.min(right_len);
+        for (i, (&a, &b)) in left_buf[..common]
+            .iter()
+            .zip(&right_buf[..common])
+            .enumerate()
+        {
+            if a != b {
+                println!("0x{:08x}: {:02x} != {:02x}", offset + i as u64, a, b);
+                differences += 1;
+            }
+        }
+        offset += common as u64;
+
+        if left_len != right_len {
+            // One file ended early: the rest of the longer one is all difference.
+            let left_total = offset + (left_len - common) as u64 + remaining_len(&mut left)?;
+            let right_total = offset + (right_len - common) as u64 + remaining_len(&mut right)?;
+            println!(
+                "Length mismatch at 0x{:08x}: {} is {} bytes, {} is {} bytes",
+                offset,
+                args.target_file.display(),
+                left_total,
+                other_file.display(),
+                right_total
+            );
+            differences += left_total.abs_diff(right_total);
+            break;
+        }
+        if left_len == 0 {
+            break;
+        }
+    }
+
+    if differences == 0 {
+        println!("✓ Files are identical ({} bytes)", offset);
+    } else {
+        println!("{} differing byte(s)", differences);
+    }
+
+    Ok(())
+}
+
+fn remaining_len(file: &mut File) -> Result<u64, String> {
+    io::copy(file, &mut io::sink()).map_err(|e| format!("Erreur I/O read: {}", e))
+}
+
+fn compute_checksum<R: Read>(reader: &mut R, algo: ChecksumAlgo) -> io::Result<(String, u64)> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut crc = crc32fast::Hasher::new();
+    let mut sha = Sha256::new();
+    let mut total = 0u64;
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        match algo {
+            ChecksumAlgo::Crc32 => crc.update(&buffer[..bytes_read]),
+            ChecksumAlgo::Sha256 => sha.update(&buffer[..bytes_read]),
+        }
+        total += bytes_read as u64;
+    }
+
+    let digest = match algo {
+        ChecksumAlgo::Crc32 => format!("{:08x}", crc.finalize()),
+        ChecksumAlgo::Sha256 => hex::encode(sha.finalize()),
+    };
+    Ok((digest, total))
+}
+
+fn handle_checksum(args: &Args, algo: ChecksumAlgo) -> Result<(), String> {
+    let region = open_region(args).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let file_len = file_len(region.get_ref())?;
+    let mut region = ProgressReader::new(region, args.offset, file_len, args.progress);
+    let (digest, total) =
+        compute_checksum(&mut region, algo).map_err(|e| format!("Erreur I/O read: {}", e))?;
+    drop(region);
+
+    println!(
+        "{} ({} bytes at offset 0x{:08x}): {}",
+        algo.label(),
+        total,
+        args.offset,
+        digest
+    );
+
+    if let Some(ref expected) = args.verify {
+        let expected = expected.as_deref().ok_or_else(|| {
+            String::from("Erreur: --verify attend le checksum attendu avec --checksum.")
+        })?;
+        let expected = expected.trim_start_matches("0x").to_lowercase();
+        if expected != digest {
+            return Err(format!(
+                "Erreur: Checksum invalide (attendu {}, obtenu {}).",
+                expected, digest
+            ));
+        }
+        println!("✓ Checksum verified");
+    }
+
+    Ok(())
+}
+
+/// Parse one `print_hex_dump` line into its offset and bytes, ignoring the
+/// ASCII column.
+fn parse_dump_line(line: &str, radix: u32) -> Option<(u64, Vec<u8>)> {
+    let (offset_part, rest) = line.split_once(':')?;
+    let offset = u64::from_str_radix(offset_part.trim(), radix).ok()?;
+    let hex_part = rest.split('|').next()?;
+    let bytes = hex_part
+        .split_whitespace()
+        .map(|token| u8::from_str_radix(token, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((offset, bytes))
+}
+
+fn handle_revert(args: &Args, dump_file: &Path) -> Result<(), String> {
+    let content = std::fs::read_to_string(dump_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+
+    let mut total = 0usize;
+    let mut end = 0u64;
+    for (line_number, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (offset, bytes) =
+            parse_dump_line(line, args.offset_format.radix()).ok_or_else(|| {
+                format!(
+                    "Erreur: Ligne {} invalide dans le dump: {:?}",
+                    line_number + 1,
+                    line
+                )
+            })?;
+        write_at(&mut file, offset, &bytes)?;
+        total += bytes.len();
+        end = end.max(offset + bytes.len() as u64);
+    }
+    file.flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+
+    println!(
+        "Reverted {} bytes from {} into {} ({} bytes)",
+        total,
+        dump_file.display(),
+        args.target_file.display(),
+        end
+    );
+    println!("✓ Successfully written");
+
+    Ok(())
+}
+
+fn handle_strings(args: &Args, min_len: usize) -> Result<(), String> {
+    let mut file = File::open(&args.target_file).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    // The current run is carried across chunks so strings can span them.
+    let mut run = String::new();
+    let mut run_start = 0u64;
+    let mut position = 0u64;
+    let mut found = 0usize;
+
+    let mut flush_run = |run: &mut String, run_start: u64| {
+        if run.len() >= min_len.max(1) {
+            println!("0x{:08x}: {}", run_start, run);
+            found += 1;
+        }
+        run.clear();
+    };
+
+    loop {
+        let bytes_read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Erreur I/O read: {}", e))?;
+        if bytes_read == 0 {
+            break;
+        }
+        for &b in &buffer[..bytes_read] {
+            if is_printable(b) {
+                if run.is_empty() {
+                    run_start = position;
+                }
+                run.push(b as char);
+            } else if !run.is_empty() {
+                flush_run(&mut run, run_start);
+            }
+            position += 1;
+        }
+    }
+    flush_run(&mut run, run_start);
+
+    if found == 0 {
+        println!("No strings of at least {} bytes found", min_len);
+    }
+
+    Ok(())
+}
+
+fn handle_xor(args: &Args, hex_key: &str) -> Result<(), String> {
+    let key = decode_hex(hex_key)?;
+    if key.is_empty() {
+        return Err(String::from("Erreur: Clé XOR vide."));
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    file.seek(SeekFrom::Start(args.offset))
+        .map_err(|e| format!("Erreur I/O seek: {}", e))?;
+
+    let mut remaining = if args.all { u64::MAX } else { args.size as u64 };
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut transformed = 0u64;
+
+    while remaining > 0 {
+        let want = remaining.min(CHUNK_SIZE as u64) as usize;
+        let bytes_read = read_full(&mut file, &mut buffer[..want])
+            .map_err(|e| format!("Erreur I/O read: {}", e))?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        // Keep the key phase continuous across chunks.
+        for (i, b) in buffer[..bytes_read].iter_mut().enumerate() {
+            *b ^= key[(transformed as usize + i) % key.len()];
+        }
+        write_at(&mut file, args.offset + transformed, &buffer[..bytes_read])?;
+
+        transformed += bytes_read as u64;
+        remaining -= bytes_read as u64;
+    }
+    file.flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+
+    println!(
+        "XOR {} bytes at offset 0x{:08x} with key {}",
+        transformed,
+        args.offset,
+        hex::encode(&key)
+    );
+    println!("✓ Successfully written");
+
+    Ok(())
+}
+
+fn handle_base64_encode(args: &Args) -> Result<(), String> {
+    let mut region = open_region(args).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let mut bytes = Vec::new();
+    region
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Erreur I/O read: {}", e))?;
+
+    // The report goes to stderr so stdout stays pure base64 for piping.
+    eprintln!(
+        "Encoding {} bytes at offset 0x{:08x}",
+        bytes.len(),
+        args.offset
+    );
+    println!("{}", BASE64.encode(&bytes));
+
+    Ok(())
+}
+
+fn handle_truncate(args: &Args, new_len: u64) -> Result<(), String> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    let old_len = file
+        .metadata()
+        .map_err(|e| format!("Erreur I/O: {}", e))?
+        .len();
+
+    file.set_len(new_len)
+        .map_err(|e| format!("Erreur I/O truncate: {}", e))?;
+
+    println!("Old size: {} bytes", old_len);
+    println!("New size: {} bytes", new_len);
+    println!("✓ Successfully truncated");
+
+    Ok(())
+}
+
+fn handle_copy(args: &Args, dest: &Path) -> Result<(), String> {
+    let mut region = open_region(args).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let mut dest_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(dest)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+
+    seek_for_write(&mut dest_file, args.dest_offset)?;
+    let copied =
+        io::copy(&mut region, &mut dest_file).map_err(|e| format!("Erreur I/O copy: {}", e))?;
+    dest_file
+        .flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+
+    println!(
+        "Copying {} bytes from {} at 0x{:08x} to {} at 0x{:08x}",
+        copied,
+        args.target_file.display(),
+        args.offset,
+        dest.display(),
+        args.dest_offset
+    );
+    if !args.all && copied < args.size as u64 {
+        println!(
+            "Source region is shorter than --size ({} of {} bytes available)",
+            copied, args.size
+        );
+    }
+    println!("✓ Successfully copied");
+
+    Ok(())
+}
+
+fn handle_patch(args: &Args, src: &Path) -> Result<(), String> {
+    let mut src_file = File::open(src).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+
+    seek_for_write(&mut file, args.offset)?;
+    let patched =
+        io::copy(&mut src_file, &mut file).map_err(|e| format!("Erreur I/O copy: {}", e))?;
+    file.flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("Erreur I/O: {}", e))?
+        .len();
+
+    println!(
+        "Patching {} bytes from {} at offset 0x{:08x}",
+        patched,
+        src.display(),
+        args.offset
+    );
+    println!("File size: {} bytes", size);
+    println!("✓ Successfully written");
+
+    Ok(())
+}
+
+fn handle_swap(args: &Args, word_width: usize) -> Result<(), String> {
+    if !args.size.is_multiple_of(word_width) {
+        return Err(format!(
+            "Erreur: --size ({}) doit être un multiple de {}.",
+            args.size, word_width
+        ));
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&args.target_file)
+        .map_err(|e| format!("Erreur I/O: {}", e))?;
+    file.seek(SeekFrom::Start(args.offset))
+        .map_err(|e| format!("Erreur I/O seek: {}", e))?;
+
+    let mut buffer = vec![0u8; args.size];
+    let bytes_read =
+        read_full(&mut file, &mut buffer).map_err(|e| format!("Erreur I/O read: {}", e))?;
+    if bytes_read != args.size {
+        return Err(format!(
+            "Erreur: Seulement {} octets disponibles à l'offset 0x{:08x} (--size {}).",
+            bytes_read, args.offset, args.size
+        ));
+    }
+
+    for word in buffer.chunks_exact_mut(word_width) {
+        word.reverse();
+    }
+    write_at(&mut file, args.offset, &buffer)?;
+    file.flush()
+        .map_err(|e| format!("Erreur I/O flush: {}", e))?;
+
+    println!(
+        "Swapped {} {}-byte word(s) at offset 0x{:08x}",
+        args.size / word_width,
+        word_width,
+        args.offset
+    );
+    println!("✓ Successfully written");
+
+    Ok(())
+}
+
+const HISTOGRAM_BAR_WIDTH: u64 = 40;
+
+fn count_bytes<R: Read>(reader: &mut R) -> io::Result<[u64; 256]> {
+    let mut counts = [0u64; 256];
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        for &b in &buffer[..bytes_read] {
+            counts[b as usize] += 1;
+        }
+    }
+    Ok(counts)
+}
+
+fn shannon_entropy(counts: &[u64; 256]) -> f64 {
+    let total: u64 = counts.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn handle_stats(args: &Args) -> Result<(), String> {
+    let mut region = open_region(args).map_err(|e| format!("Erreur I/O: {}", e))?;
+    let counts = count_bytes(&mut region).map_err(|e| format!("Erreur I/O read: {}", e))?;
+    let total: u64 = counts.iter().sum();
+
+    println!("{} bytes at offset 0x{:08x}", total, args.offset);
+
+    if args.histogram {
+        let max = counts.iter().copied().max().unwrap_or(0).max(1);
+        println!("\nHistogram (non-zero values):");
+        for (value, &count) in counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+            let bar_len = (count * HISTOGRAM_BAR_WIDTH).div_ceil(max) as usize;
+            println!(
+                "0x{:02x} {} {:>10} {}",
+                value,
+                format_ascii(value as u8),
+                count,
+                "█".repeat(bar_len)
+            );
+        }
+    }
+
+    if args.entropy {
+        println!("\nEntropy: {:.4} bits/byte", shannon_entropy(&counts));
+    }
+
     Ok(())
 }
 
 fn main() -> Result<(), String> {
-    let args = Args::parse();
+    let mut args = Args::parse();
+    args.offset = resolve_offset(&args.target_file, args.offset_spec)?;
+
+    if args.checksum_algo.is_none() && matches!(args.verify, Some(Some(_))) {
+        return Err(String::from(
+            "Erreur: --verify HEX n'est accepté qu'avec --checksum; en mode écriture, utilisez --verify sans valeur.",
+        ));
+    }
 
     if args.read_mode {
         handle_read(&args).map_err(|e| format!("Erreur de lecture: {}", e))
     } else if let Some(ref hex_string) = args.write_hex_string {
-        handle_write(&args, hex_string)
+        handle_write(&args, &decode_hex(hex_string)?)
+    } else if let Some(ref text) = args.write_ascii {
+        handle_write(&args, text.as_bytes())
+    } else if let Some(ref hex_file) = args.write_hex_file {
+        handle_write(&args, &read_hex_file(hex_file)?)
+    } else if let Some(ref encoded) = args.base64_decode {
+        let bytes = BASE64
+            .decode(encoded.trim())
+            .map_err(|e| format!("Erreur: Chaîne base64 invalide ({}).", e))?;
+        handle_write(&args, &bytes)
+    } else if args.base64_encode {
+        handle_base64_encode(&args)
+    } else if let Some(ref hex_string) = args.search_hex {
+        handle_search(&args, hex_string)
+    } else if let Some(ref hex_string) = args.count_hex {
+        handle_count(&args, hex_string)
+    } else if let Some(ref spec) = args.replace_spec {
+        handle_replace(&args, spec)
+    } else if let Some(ref hex_string) = args.fill_hex {
+        handle_fill(&args, hex_string)
+    } else if let Some(ref other_file) = args.diff_file {
+        handle_diff(&args, other_file)
+    } else if let Some(algo) = args.checksum_algo {
+        handle_checksum(&args, algo)
+    } else if let Some(ref dump_file) = args.revert_dump {
+        handle_revert(&args, dump_file)
+    } else if let Some(min_len) = args.strings_min_len {
+        handle_strings(&args, min_len)
+    } else if let Some(count) = args.tail_len {
+        handle_tail(&args, count).map_err(|e| format!("Erreur de lecture: {}", e))
+    } else if let Some(ref dest) = args.copy_dest {
+        handle_copy(&args, dest)
+    } else if let Some(ref src) = args.patch_src {
+        handle_patch(&args, src)
+    } else if let Some(word_width) = args.swap_width {
+        handle_swap(&args, word_width)
+    } else if args.redact {
+        handle_redact(&args)
+    } else if let Some(new_len) = args.truncate_len {
+        handle_truncate(&args, new_len)
+    } else if let Some(ref hex_key) = args.xor_key {
+        handle_xor(&args, hex_key)
+    } else if args.histogram || args.entropy {
+        handle_stats(&args)
     } else {
         Err(String::from(
-            "Erreur: Vous devez spécifier le mode --read (-r) ou --write (-w).",
+            "Erreur: Vous devez spécifier un mode, par exemple --read (-r) ou --write (-w) (voir --help).",
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hextool_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn write_past_eof_zero_fills_gap() {
+        let path = temp_path("zero_fill");
+        std::fs::write(&path, b"abcd").unwrap();
+
+        let mut args = Args::try_parse_from([
+            "hextool",
+            "-f",
+            path.to_str().unwrap(),
+            "-w",
+            "ff",
+            "-o",
+            "0x10000",
+        ])
+        .unwrap();
+        args.offset = resolve_offset(&args.target_file, args.offset_spec).unwrap();
+        handle_write(&args, &[0xff]).unwrap();
+
+        let content = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(content.len(), 0x10001);
+        assert_eq!(&content[..4], b"abcd");
+        assert!(content[4..0x10000].iter().all(|&b| b == 0x00));
+        assert_eq!(content[0x10000], 0xff);
+    }
+
+    #[test]
+    fn append_requires_a_write_mode() {
+        assert!(Args::try_parse_from(["hextool", "-f", "x", "-r", "--append"]).is_err());
+        assert!(Args::try_parse_from(["hextool", "-f", "x", "-w", "ff", "--append"]).is_ok());
+    }
+}
@@ -0,0 +1,432 @@
+//! Crypto primitives behind streamchat: Diffie-Hellman arithmetic, the
+//! keystreams and the XOR stream cipher built on them, key derivation and
+//! message authentication. None of it touches the network, so it can be
+//! tested in-process.
+
+use aes::Aes256;
+use chacha20::cipher::{KeyIvInit, StreamCipher as _, StreamCipherSeek};
+use chacha20::ChaCha20;
+use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+// Hardcoded DH parameters (64-bit prime - public)
+pub const P: u64 = 0xD87FA3E291B4C7F3;
+pub const G: u64 = 2;
+
+// RFC 3526 group 14: 2048-bit MODP prime, generator 2
+pub const MODP_2048_P: &str = "\
+    FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+    020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+    4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+    EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE45B3DC2007CB8A163BF05\
+    98DA48361C55D39A69163FA8FD24CF5F83655D23DCA3AD961C62F356208552BB\
+    9ED529077096966D670C354E4ABC9804F1746C08CA18217C32905E462E36CE3B\
+    E39E772C180E86039B2783A2EC07A28FB5C55DF06F4C52C9DE2BCBF695581718\
+    3995497CEA956AE515D2261898FA051015728E5A8AACAA68FFFFFFFFFFFFFFFF";
+pub const MODP_2048_BYTES: usize = 256;
+
+// Size of the private exponent used with the 2048-bit group
+pub const STRONG_PRIVATE_BYTES: usize = 32;
+
+// LCG parameters for keystream generation
+pub const LCG_A: u64 = 1103515245;
+pub const LCG_C: u64 = 12345;
+pub const LCG_M: u64 = 1u64 << 32;
+
+// Domain-separation label for the human-readable session fingerprint
+pub const FINGERPRINT_LABEL: &str = "streamchat fingerprint";
+
+// HMAC-SHA256 tag appended to every ciphertext
+pub const MAC_LEN: usize = 32;
+
+// Per-message nonce sent in front of the ciphertext: a random 4-byte session
+// prefix followed by the 64-bit message sequence number, so no two messages in
+// the same direction ever share keystream bytes. The nonce is covered by the
+// HMAC, which lets the receiver trust the sequence number for replay checks.
+pub const NONCE_LEN: usize = 12;
+
+/// Modular exponentiation: (base^exp) mod modulus
+///
+/// Intermediate products are computed in `u128`. Both factors are reduced
+/// below `modulus`, so a product is at most (2^64 - 1)^2 < 2^128 and cannot
+/// overflow for any `u64` modulus. Wider moduli go through `mod_exp_big`.
+pub fn mod_exp(base: u64, exp: u64, modulus: u64) -> u64 {
+    debug_assert!(modulus != 0, "mod_exp: modulus must be non-zero");
+    let modulus = modulus as u128;
+    let mut result = 1u128 % modulus;
+    let mut base = base as u128 % modulus;
+    let mut exp = exp;
+
+    while exp > 0 {
+        if exp % 2 == 1 {
+            result = (result * base) % modulus;
+        }
+        exp >>= 1;
+        base = (base * base) % modulus;
+        debug_assert!(result < modulus && base < modulus);
+    }
+
+    result as u64
+}
+
+/// Public key for a 64-bit DH private key: G^private mod P.
+pub fn dh_public_key(private_key: u64) -> u64 {
+    mod_exp(G, private_key, P)
+}
+
+/// Shared secret both peers reach: their_public^our_private mod P.
+pub fn dh_shared_secret(their_public: u64, our_private: u64) -> u64 {
+    mod_exp(their_public, our_private, P)
+}
+
+/// 64 random bits from the operating system's CSPRNG.
+pub fn generate_random() -> u64 {
+    OsRng.next_u64()
+}
+
+/// Source of the bytes XORed into the plaintext.
+pub trait Keystream: Send {
+    fn next_byte(&mut self) -> u8;
+    /// Upcoming bytes, without advancing the stream.
+    fn peek_bytes(&self, count: usize) -> Vec<u8>;
+}
+
+/// Parameters of the LCG recurrence state = (a * state + c) mod m.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LcgParams {
+    pub a: u64,
+    pub c: u64,
+    /// Must be non-zero; the state, and so the keystream, repeats within m steps.
+    pub m: u64,
+}
+
+impl Default for LcgParams {
+    fn default() -> Self {
+        Self {
+            a: LCG_A,
+            c: LCG_C,
+            m: LCG_M,
+        }
+    }
+}
+
+impl LcgParams {
+    fn step(&self, state: u64) -> u64 {
+        ((state as u128 * self.a as u128 + self.c as u128) % self.m as u128) as u64
+    }
+}
+
+pub struct KeystreamGenerator {
+    state: u64,
+    params: LcgParams,
+}
+
+impl KeystreamGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self::with_params(seed, LcgParams::default())
+    }
+
+    pub fn with_params(seed: u64, params: LcgParams) -> Self {
+        assert!(params.m != 0, "LCG modulus must be non-zero");
+        Self {
+            state: seed,
+            params,
+        }
+    }
+}
+
+impl Keystream for KeystreamGenerator {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.params.step(self.state);
+        (self.state & 0xFF) as u8
+    }
+
+    fn peek_bytes(&self, count: usize) -> Vec<u8> {
+        let mut temp_state = self.state;
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            temp_state = self.params.step(temp_state);
+            bytes.push((temp_state & 0xFF) as u8);
+        }
+        bytes
+    }
+}
+
+/// ChaCha20 keystream keyed with a hash of the shared secret.
+pub struct ChaChaKeystream {
+    key: [u8; 32],
+    nonce: [u8; NONCE_LEN],
+    cipher: ChaCha20,
+    position: u64,
+}
+
+impl ChaChaKeystream {
+    pub fn new(key: [u8; 32], nonce: [u8; NONCE_LEN]) -> Self {
+        Self {
+            key,
+            nonce,
+            cipher: ChaCha20::new(&key.into(), &nonce.into()),
+            position: 0,
+        }
+    }
+}
+
+impl Keystream for ChaChaKeystream {
+    fn next_byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.cipher.apply_keystream(&mut byte);
+        self.position += 1;
+        byte[0]
+    }
+
+    fn peek_bytes(&self, count: usize) -> Vec<u8> {
+        let mut cipher = ChaCha20::new(&self.key.into(), &self.nonce.into());
+        cipher.seek(self.position);
+        let mut bytes = vec![0u8; count];
+        cipher.apply_keystream(&mut bytes);
+        bytes
+    }
+}
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// AES-256-CTR keystream. The 16-byte counter block is the message nonce
+/// followed by a 32-bit block counter starting at zero.
+pub struct AesCtrKeystream {
+    key: [u8; 32],
+    iv: [u8; 16],
+    cipher: Aes256Ctr,
+    position: u64,
+}
+
+impl AesCtrKeystream {
+    pub fn new(key: [u8; 32], nonce: [u8; NONCE_LEN]) -> Self {
+        let mut iv = [0u8; 16];
+        iv[..NONCE_LEN].copy_from_slice(&nonce);
+        Self {
+            key,
+            iv,
+            cipher: Aes256Ctr::new(&key.into(), &iv.into()),
+            position: 0,
+        }
+    }
+}
+
+impl Keystream for AesCtrKeystream {
+    fn next_byte(&mut self) -> u8 {
+        let mut byte = [0u8];
+        self.cipher.apply_keystream(&mut byte);
+        self.position += 1;
+        byte[0]
+    }
+
+    fn peek_bytes(&self, count: usize) -> Vec<u8> {
+        let mut cipher = Aes256Ctr::new(&self.key.into(), &self.iv.into());
+        cipher.seek(self.position);
+        let mut bytes = vec![0u8; count];
+        cipher.apply_keystream(&mut bytes);
+        bytes
+    }
+}
+
+/// Stretch the DH shared secret into a 256-bit key: SHA-256(label || secret).
+/// Both peers run the same derivation so their keystreams match.
+pub fn derive_key(shared_secret: &[u8], label: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(label.as_bytes());
+    hasher.update(shared_secret);
+    hasher.finalize().into()
+}
+
+/// Short digest of the shared secret, grouped for reading aloud, e.g.
+/// "3F2A 9C01 77DE 4B10". Peers compare it out-of-band: a man-in-the-middle
+/// ends up with a different secret on each side, so the fingerprints differ.
+pub fn fingerprint(shared_secret: &[u8]) -> String {
+    let digest = derive_key(shared_secret, FINGERPRINT_LABEL);
+    digest[..8]
+        .chunks(2)
+        .map(|pair| format!("{:02X}{:02X}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whole-message encryption. Sealing and opening messages only go through
+/// this trait, so adding a cipher never touches the chat loops.
+pub trait StreamCipher: Send {
+    fn encrypt(&mut self, data: &[u8]) -> Vec<u8>;
+    fn decrypt(&mut self, data: &[u8]) -> Vec<u8>;
+}
+
+/// XOR of the data with a keystream; decryption is the same operation.
+pub struct XorCipher {
+    keystream: Box<dyn Keystream>,
+}
+
+impl XorCipher {
+    pub fn new(keystream: Box<dyn Keystream>) -> Self {
+        Self { keystream }
+    }
+}
+
+impl StreamCipher for XorCipher {
+    fn encrypt(&mut self, data: &[u8]) -> Vec<u8> {
+        xor_cipher(data, self.keystream.as_mut())
+    }
+
+    fn decrypt(&mut self, data: &[u8]) -> Vec<u8> {
+        xor_cipher(data, self.keystream.as_mut())
+    }
+}
+
+pub fn xor_cipher(data: &[u8], keystream: &mut dyn Keystream) -> Vec<u8> {
+    data.iter().map(|&b| b ^ keystream.next_byte()).collect()
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub fn compute_mac(mac_key: &[u8; 32], data: &[u8]) -> [u8; MAC_LEN] {
+    let mut mac = HmacSha256::new_from_slice(mac_key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// Constant-time check of `tag` against the HMAC of `data`.
+pub fn verify_mac(mac_key: &[u8; 32], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(mac_key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+pub fn modp_2048_prime() -> BigUint {
+    BigUint::parse_bytes(MODP_2048_P.as_bytes(), 16).expect("valid MODP prime")
+}
+
+/// Big-integer modular exponentiation for moduli that don't fit in 64 bits.
+pub fn mod_exp_big(base: &BigUint, exp: &BigUint, modulus: &BigUint) -> BigUint {
+    base.modpow(exp, modulus)
+}
+
+/// Big-endian bytes left-padded to the size of the 2048-bit group.
+pub fn to_group_bytes(value: &BigUint) -> Vec<u8> {
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0u8; MODP_2048_BYTES - bytes.len()];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mod_exp_known_vectors() {
+        assert_eq!(mod_exp(4, 13, 497), 445);
+        assert_eq!(mod_exp(2, 10, 1000), 24);
+        assert_eq!(mod_exp(3, 200, 50), 1);
+        assert_eq!(mod_exp(7, 0, 13), 1);
+        assert_eq!(mod_exp(5, 3, 1), 0);
+        // Operands near 2^64 exercise the full width of the u128 products.
+        assert_eq!(
+            mod_exp(0xFFFF_FFFF_FFFF_FFFE, u64::MAX, 0xFFFF_FFFF_FFFF_FFC5),
+            0x1BEC_527C_2AC3_E986
+        );
+    }
+
+    #[test]
+    fn mod_exp_matches_bigint_path() {
+        for &(base, exp) in &[
+            (G, 0x0123_4567_89AB_CDEF),
+            (P - 1, P - 2),
+            (u64::MAX, 65537),
+        ] {
+            let big = mod_exp_big(&BigUint::from(base), &BigUint::from(exp), &BigUint::from(P));
+            assert_eq!(BigUint::from(mod_exp(base, exp, P)), big);
+        }
+    }
+
+    #[test]
+    fn dh_peers_agree_on_the_secret() {
+        for _ in 0..16 {
+            let (alice_private, bob_private) = (generate_random(), generate_random());
+            let alice_public = dh_public_key(alice_private);
+            let bob_public = dh_public_key(bob_private);
+
+            let alice_secret = dh_shared_secret(bob_public, alice_private);
+            let bob_secret = dh_shared_secret(alice_public, bob_private);
+            assert_eq!(alice_secret, bob_secret);
+            assert_eq!(
+                fingerprint(&alice_secret.to_be_bytes()),
+                fingerprint(&bob_secret.to_be_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn strong_dh_peers_agree_on_the_secret() {
+        let p = modp_2048_prime();
+        let g = BigUint::from(G);
+        let alice_private = BigUint::from_bytes_be(&[0x5A; STRONG_PRIVATE_BYTES]);
+        let bob_private = BigUint::from_bytes_be(&[0xC3; STRONG_PRIVATE_BYTES]);
+        let alice_public = mod_exp_big(&g, &alice_private, &p);
+        let bob_public = mod_exp_big(&g, &bob_private, &p);
+
+        let alice_secret = to_group_bytes(&mod_exp_big(&bob_public, &alice_private, &p));
+        let bob_secret = to_group_bytes(&mod_exp_big(&alice_public, &bob_private, &p));
+        assert_eq!(alice_secret.len(), MODP_2048_BYTES);
+        assert_eq!(alice_secret, bob_secret);
+    }
+
+    #[test]
+    fn stream_ciphers_round_trip_through_the_trait() {
+        let key = derive_key(&0x0123_4567_89AB_CDEFu64.to_be_bytes(), "test key");
+        let nonce = [7u8; NONCE_LEN];
+        let plain = b"attack at dawn, bring snacks";
+        let make = |name: &str| -> Box<dyn Keystream> {
+            match name {
+                "lcg" => Box::new(KeystreamGenerator::new(0xDEAD_BEEF)),
+                "chacha20" => Box::new(ChaChaKeystream::new(key, nonce)),
+                _ => Box::new(AesCtrKeystream::new(key, nonce)),
+            }
+        };
+
+        for name in ["lcg", "chacha20", "aes-ctr"] {
+            let mut sender: Box<dyn StreamCipher> = Box::new(XorCipher::new(make(name)));
+            let mut receiver: Box<dyn StreamCipher> = Box::new(XorCipher::new(make(name)));
+
+            let encrypted = sender.encrypt(plain);
+            assert_ne!(encrypted, plain, "{} left the plaintext unchanged", name);
+            assert_eq!(receiver.decrypt(&encrypted), plain, "{}", name);
+        }
+    }
+
+    #[test]
+    fn peek_bytes_does_not_advance_the_stream() {
+        let mut keystream = KeystreamGenerator::new(42);
+        let peeked = keystream.peek_bytes(4);
+        let taken: Vec<u8> = (0..4).map(|_| keystream.next_byte()).collect();
+        assert_eq!(peeked, taken);
+    }
+
+    #[test]
+    fn custom_lcg_parameters_drive_the_keystream() {
+        // a = 1, c = 3, m = 10 just counts up by 3 modulo 10.
+        let params = LcgParams { a: 1, c: 3, m: 10 };
+        let mut keystream = KeystreamGenerator::with_params(0, params);
+        let bytes: Vec<u8> = (0..5).map(|_| keystream.next_byte()).collect();
+        assert_eq!(bytes, [3, 6, 9, 2, 5]);
+
+        let mut default = KeystreamGenerator::new(1);
+        assert_eq!(default.next_byte(), ((LCG_A + LCG_C) % LCG_M) as u8);
+    }
+
+    #[test]
+    fn mac_rejects_tampered_data() {
+        let mac_key = derive_key(b"shared secret", "mac");
+        let tag = compute_mac(&mac_key, b"hello");
+        assert!(verify_mac(&mac_key, b"hello", &tag));
+        assert!(!verify_mac(&mac_key, b"hellp", &tag));
+    }
+}
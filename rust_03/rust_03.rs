@@ -1,6 +1,49 @@
-use clap::Parser;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use clap::{CommandFactory, Parser, ValueEnum};
+use num_bigint::BigUint;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::hint;
 use std::io::{self, BufRead, BufReader, Read, Write};
-use std::net::{TcpListener, TcpStream};
+use std::iter;
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use streamchat::{
+    compute_mac, derive_key, dh_public_key, dh_shared_secret, fingerprint, generate_random,
+    mod_exp, mod_exp_big, modp_2048_prime, to_group_bytes, verify_mac, AesCtrKeystream,
+    ChaChaKeystream, Keystream, KeystreamGenerator, LcgParams, StreamCipher, XorCipher, G, LCG_A,
+    LCG_C, LCG_M, MAC_LEN, MODP_2048_BYTES, MODP_2048_P, NONCE_LEN, P, STRONG_PRIVATE_BYTES,
+};
+
+// Set once by --quiet; silences the step-by-step crypto walkthrough
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// `println!` for the educational [DH]/[STREAM]/[ENCRYPT]/... output.
+macro_rules! debug_println {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `print!` counterpart of `debug_println!`.
+macro_rules! debug_print {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            print!($($arg)*);
+        }
+    };
+}
 
 /// Stream cipher chat with Diffie-Hellman key generation
 #[derive(Parser, Debug)]
@@ -8,6 +51,205 @@ use std::net::{TcpListener, TcpStream};
 struct Args {
     #[command(subcommand)]
     command: Command,
+
+    /// Keystream cipher (both peers must use the same one)
+    #[arg(long, value_enum, default_value = "chacha20", global = true)]
+    cipher: CipherKind,
+
+    /// Use the 2048-bit MODP group from RFC 3526 instead of the 64-bit demo prime
+    #[arg(long, global = true)]
+    strong_dh: bool,
+
+    /// Handle shown to the other side of the chat
+    #[arg(long, default_value = "anon", global = true, value_parser = parse_handle)]
+    name: String,
+
+    /// Prefix displayed messages with the time (HH:MM:SS, UTC)
+    #[arg(long, global = true)]
+    timestamps: bool,
+
+    /// Append every sent and received message, in plaintext, to this file
+    #[arg(long, global = true, value_name = "PATH")]
+    log: Option<PathBuf>,
+
+    /// Only show chat messages, warnings and the fingerprint, not the crypto walkthrough
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Give up on a peer that sends nothing, not even a heartbeat, for this long
+    #[arg(long, value_name = "SECS", global = true, value_parser = clap::value_parser!(u64).range(MIN_TIMEOUT_SECS..))]
+    timeout: Option<u64>,
+
+    /// Send each payload as a 4-byte length plus raw bytes instead of a text line
+    #[arg(long, global = true)]
+    binary_frames: bool,
+
+    /// Text encoding of each payload line (both peers must use the same one)
+    #[arg(long, value_enum, default_value_t = Encoding::Hex, global = true, conflicts_with = "binary_frames")]
+    encoding: Encoding,
+
+    /// LCG multiplier for --cipher lcg-insecure (both peers must match)
+    #[arg(long, value_name = "A", default_value_t = LCG_A, global = true)]
+    lcg_a: u64,
+
+    /// LCG increment for --cipher lcg-insecure (both peers must match)
+    #[arg(long, value_name = "C", default_value_t = LCG_C, global = true)]
+    lcg_c: u64,
+
+    /// LCG modulus for --cipher lcg-insecure (both peers must match)
+    #[arg(long, value_name = "M", default_value_t = LCG_M, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    lcg_m: u64,
+
+    /// Ratchet each direction's key forward after this many messages
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_REKEY_EVERY, global = true, value_parser = clap::value_parser!(u64).range(1..))]
+    rekey_every: u64,
+
+    /// Where files received with /send are saved
+    #[arg(long, value_name = "DIR", default_value = "received", global = true)]
+    download_dir: PathBuf,
+}
+
+/// Settings shared by every connection of a run.
+#[derive(Clone, Debug)]
+struct ChatOptions {
+    cipher: CipherKind,
+    lcg: LcgParams,
+    strong_dh: bool,
+    name: String,
+    timestamps: bool,
+    log: Option<Transcript>,
+    timeout: Option<Duration>,
+    framing: Framing,
+    rekey_every: u64,
+    download_dir: PathBuf,
+}
+
+impl ChatOptions {
+    /// "[HH:MM:SS] " in UTC when timestamps are on, otherwise empty. Display only:
+    /// nothing time-related goes on the wire.
+    fn stamp(&self) -> String {
+        if !self.timestamps {
+            return String::new();
+        }
+        let secs = unix_time() % 86_400;
+        format!(
+            "[{:02}:{:02}:{:02}] ",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        )
+    }
+
+    /// One direction of a session, keyed from `shared_secret` and `label`.
+    fn direction(&self, shared_secret: &[u8], label: &str) -> Direction {
+        Direction::new(self.cipher, shared_secret, label)
+            .with_lcg(self.lcg)
+            .with_rekey_every(self.rekey_every)
+    }
+
+    /// Show a message we sent as "[you] ...", in the same format as the
+    /// peer's messages. Local only; chat lines are not protocol output, so
+    /// --quiet keeps them.
+    fn echo_sent(&self, message: &str) {
+        println!("{}[you] {}", self.stamp(), indent_lines(message));
+    }
+
+    /// Push anything buffered to the transcript before exiting.
+    fn flush_log(&self) {
+        if let Some(log) = &self.log {
+            log.flush();
+        }
+    }
+
+    /// Append a message to the transcript, if one was requested.
+    fn log(&self, direction: &str, sender: &str, message: &str) {
+        if let Some(log) = &self.log {
+            log.record(direction, sender, message);
+        }
+    }
+}
+
+/// Plaintext record of a session (--log), shared by every thread that
+/// sends or receives. Opened in append mode so sessions accumulate.
+#[derive(Clone, Debug)]
+struct Transcript {
+    file: Arc<Mutex<File>>,
+}
+
+impl Transcript {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    fn flush(&self) {
+        if let Err(e) = self.file.lock().unwrap().sync_all() {
+            println!("[WARN] Could not flush the log: {}", e);
+        }
+    }
+
+    fn record(&self, direction: &str, sender: &str, message: &str) {
+        let mut file = self.file.lock().unwrap();
+        let result = writeln!(
+            file,
+            "[{}] {} {}: {}",
+            utc_datetime(unix_time()),
+            direction,
+            sender,
+            indent_lines(message)
+        )
+        .and_then(|_| file.flush());
+        if let Err(e) = result {
+            eprintln!("[WARN] Could not write to the log: {}", e);
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// "YYYY-MM-DD HH:MM:SS UTC" for a Unix timestamp (civil-from-days algorithm).
+fn utc_datetime(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum CipherKind {
+    /// ChaCha20 keyed with SHA-256(shared secret)
+    Chacha20,
+    /// AES-256 in counter mode keyed with SHA-256(shared secret)
+    AesCtr,
+    /// 32-bit LCG seeded with the shared secret (educational, predictable)
+    LcgInsecure,
 }
 
 #[derive(Parser, Debug)]
@@ -16,327 +258,2083 @@ enum Command {
     Server {
         /// Port to listen on
         port: u16,
+
+        /// Address to listen on: localhost only by default, 0.0.0.0 for every interface
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+        bind: IpAddr,
+
+        /// Accept any number of clients and relay each message to all the others
+        #[arg(long)]
+        broadcast: bool,
     },
     /// Connect to server
     Client {
-        /// Server address (host:port)
+        /// Server address: host:port, IPv4:port or [IPv6]:port
         address: String,
     },
+    /// Time modular exponentiation (the 2048-bit group with --strong-dh)
+    Bench {
+        /// Number of exponentiations to time [default: 100000, or 1000 with --strong-dh]
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        iterations: Option<u64>,
+    },
+}
+
+// Default bench sizes; a 2048-bit exponentiation costs about a thousand
+// times more than a 64-bit one
+const BENCH_ITERATIONS: u64 = 100_000;
+const STRONG_BENCH_ITERATIONS: u64 = 1_000;
+
+// Typed on either side to leave the chat
+const QUIT_COMMAND: &str = "/quit";
+
+// Longest handle accepted from the command line or from a peer
+const MAX_HANDLE_LEN: usize = 32;
+
+// An idle sender emits a heartbeat this often, so a peer using --timeout
+// can tell a quiet connection from a dead one
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+// Shortest accepted --timeout: two heartbeat intervals
+const MIN_TIMEOUT_SECS: u64 = 10;
+
+// Largest payload accepted in --binary-frames mode
+const MAX_FRAME_LEN: usize = 1 << 20;
+
+// A line holding only this starts a multi-line message; the next such line
+// ends it. The lines in between are sent as one message joined with '\n', and
+// the receiver displays them indented under the sender's name.
+const MULTILINE_DELIMITER: &str = "\"\"\"";
+
+// `/whisper <HANDLE> <MESSAGE>` in broadcast mode reaches only that client
+const WHISPER_COMMAND: &str = "/whisper";
+
+// `/send <PATH>` transfers a file to the peer
+const SEND_COMMAND: &str = "/send";
+
+// File contents travel in chunks of this size, one message each
+const FILE_CHUNK_SIZE: usize = 32 * 1024;
+
+// Messages a writer thread may have queued before senders wait for it, so a
+// large /send streams from disk instead of piling up in memory
+const OUTGOING_QUEUE_LEN: usize = 16;
+
+// Domain-separation labels for the stream keys, one per direction so the
+// two peers never XOR with the same keystream bytes
+const CLIENT_TO_SERVER_LABEL: &str = "streamchat stream key client->server";
+const SERVER_TO_CLIENT_LABEL: &str = "streamchat stream key server->client";
+
+// Domain-separation label for the hash ratchet that replaces a stream key
+// every --rekey-every messages
+const REKEY_LABEL: &str = "streamchat rekey";
+
+// Messages per key unless --rekey-every says otherwise
+const DEFAULT_REKEY_EVERY: u64 = 1_000_000;
+
+// First byte of the settings message sent after the key exchange; bumped
+// whenever its layout changes so mismatched builds fail with a clear error
+const PROTOCOL_VERSION: u8 = 1;
+
+// Settings message: version, cipher, framing, --rekey-every, LCG a/c/m
+const SETTINGS_LEN: usize = 3 + 8 + 3 * 8;
+
+// Domain-separation label for the message authentication key
+const MAC_KEY_LABEL: &str = "streamchat mac key";
+
+/// Text encoding of a payload line.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Encoding {
+    /// Two hex digits per byte
+    Hex,
+    /// Standard base64, about 1.33 characters per byte
+    Base64,
 }
 
-// Hardcoded DH parameters (64-bit prime - public)
-const P: u64 = 0xD87FA3E291B4C7F3;
-const G: u64 = 2;
+impl Encoding {
+    fn encode(self, payload: &[u8]) -> String {
+        match self {
+            Encoding::Hex => hex::encode(payload),
+            Encoding::Base64 => BASE64.encode(payload),
+        }
+    }
 
-// LCG parameters for keystream generation
-const LCG_A: u64 = 1103515245;
-const LCG_C: u64 = 12345;
-const LCG_M: u64 = 1u64 << 32;
+    /// Decoded payload; malformed text becomes an empty payload, which is
+    /// then rejected as a malformed message.
+    fn decode(self, line: &str) -> Vec<u8> {
+        match self {
+            Encoding::Hex => hex::decode(line).unwrap_or_default(),
+            Encoding::Base64 => BASE64.decode(line).unwrap_or_default(),
+        }
+    }
+}
 
-/// Modular exponentiation: (base^exp) mod modulus
-fn mod_exp(base: u64, exp: u64, modulus: u64) -> u64 {
-    let mut result = 1u128;
-    let mut base = base as u128;
-    let mut exp = exp;
-    let modulus = modulus as u128;
+/// How sealed payloads are delimited on the wire. Both peers must agree.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Framing {
+    /// One encoded payload per line: readable with netcat, but larger
+    Lines(Encoding),
+    /// 4-byte big-endian length, then the raw payload
+    Binary,
+}
 
-    base %= modulus;
+impl Framing {
+    fn write(self, stream: &mut TcpStream, payload: &[u8]) -> io::Result<()> {
+        match self {
+            Framing::Lines(encoding) => {
+                stream.write_all(encoding.encode(payload).as_bytes())?;
+                stream.write_all(b"\n")?;
+            }
+            Framing::Binary => {
+                stream.write_all(&(payload.len() as u32).to_be_bytes())?;
+                stream.write_all(payload)?;
+            }
+        }
+        stream.flush()
+    }
 
-    while exp > 0 {
-        if exp % 2 == 1 {
-            result = (result * base) % modulus;
+    /// Next payload, or `None` once the peer has closed the connection.
+    fn read(self, reader: &mut BufReader<TcpStream>) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Framing::Lines(encoding) => loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return Ok(Some(encoding.decode(line.trim())));
+                }
+            },
+            Framing::Binary => {
+                let mut len_buf = [0u8; 4];
+                match reader.read_exact(&mut len_buf) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+                let len = u32::from_be_bytes(len_buf) as usize;
+                if len > MAX_FRAME_LEN {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("frame too large ({} bytes)", len),
+                    ));
+                }
+                let mut payload = vec![0u8; len];
+                reader.read_exact(&mut payload)?;
+                Ok(Some(payload))
+            }
         }
-        exp >>= 1;
-        base = (base * base) % modulus;
     }
+}
 
-    result as u64
+/// What a decrypted message carries, stored in its first plaintext byte.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MessageKind {
+    /// A chat line
+    Text = 0,
+    /// The sender is leaving; no more messages will follow
+    Leave = 1,
+    /// The sender's handle, sent once right after the key exchange
+    Hello = 2,
+    /// Keepalive sent when the sender has been idle; never displayed
+    Heartbeat = 3,
+    /// Start of a file transfer: 8-byte big-endian size, then the file name
+    FileStart = 4,
+    /// Next piece of the file being transferred
+    FileChunk = 5,
+    /// The file being transferred is complete
+    FileEnd = 6,
 }
 
-fn generate_random() -> u64 {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let nanos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    ((nanos ^ (nanos >> 64)) & 0xFFFFFFFFFFFFFFFF) as u64
+impl MessageKind {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(MessageKind::Text),
+            1 => Some(MessageKind::Leave),
+            2 => Some(MessageKind::Hello),
+            3 => Some(MessageKind::Heartbeat),
+            4 => Some(MessageKind::FileStart),
+            5 => Some(MessageKind::FileChunk),
+            6 => Some(MessageKind::FileEnd),
+            _ => None,
+        }
+    }
 }
 
-struct KeystreamGenerator {
-    state: u64,
+fn parse_handle(s: &str) -> Result<String, String> {
+    let handle = s.trim();
+    if handle.is_empty() {
+        return Err("handle cannot be empty".to_string());
+    }
+    if handle.chars().count() > MAX_HANDLE_LEN {
+        return Err(format!(
+            "handle must be at most {} characters",
+            MAX_HANDLE_LEN
+        ));
+    }
+    if handle.chars().any(char::is_control) {
+        return Err("handle must not contain control characters".to_string());
+    }
+    Ok(handle.to_string())
 }
 
-impl KeystreamGenerator {
-    fn new(seed: u64) -> Self {
-        println!("[STREAM] Generating keystream from secret...");
-        println!("Algorithm: LCG (a={}, c={}, m=2^32)", LCG_A, LCG_C);
-        println!("Seed: secret = {:016X}", seed);
-        Self { state: seed }
+/// Make a handle received from the network safe to print.
+fn sanitize_handle(raw: &str) -> String {
+    let handle: String = raw
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_HANDLE_LEN)
+        .collect();
+    if handle.is_empty() {
+        "anon".to_string()
+    } else {
+        handle
     }
+}
 
-    fn next_byte(&mut self) -> u8 {
-        self.state = ((self.state as u128 * LCG_A as u128 + LCG_C as u128) % LCG_M as u128) as u64;
-        (self.state & 0xFF) as u8
+/// Next message typed on stdin: one line, or every line between two
+/// `MULTILINE_DELIMITER` lines joined with '\n'. A multi-line message cut
+/// short by end of input is sent as far as it got.
+fn next_message(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+) -> Option<io::Result<String>> {
+    let first = match lines.next()? {
+        Ok(line) => line,
+        Err(e) => return Some(Err(e)),
+    };
+    if first.trim() != MULTILINE_DELIMITER {
+        return Some(Ok(first));
+    }
+    let mut body = Vec::new();
+    for line in lines {
+        match line {
+            Ok(line) if line.trim() == MULTILINE_DELIMITER => break,
+            Ok(line) => body.push(line),
+            Err(e) => return Some(Err(e)),
+        }
     }
+    Some(Ok(body.join("\n")))
+}
+
+/// Indent the continuation lines of a multi-line message so they stay
+/// visually attached to the first one.
+fn indent_lines(message: &str) -> String {
+    message.replace('\n', "\n    ")
+}
 
-    fn peek_bytes(&self, count: usize) -> Vec<u8> {
-        let mut temp_state = self.state;
-        let mut bytes = Vec::new();
-        for _ in 0..count {
-            temp_state =
-                ((temp_state as u128 * LCG_A as u128 + LCG_C as u128) % LCG_M as u128) as u64;
-            bytes.push((temp_state & 0xFF) as u8);
+/// What `receive_loop` reports to its caller. Heartbeats and file transfers
+/// are handled inside the loop.
+enum ChatEvent<'a> {
+    Hello(&'a str),
+    Text(&'a str),
+    Leave,
+}
+
+/// A message waiting in a writer thread's queue: its kind and raw body.
+type Outgoing = (MessageKind, Vec<u8>);
+
+/// Byte counter for a file transfer, redrawn in place on stderr.
+struct Progress {
+    label: String,
+    total: u64,
+    done: u64,
+}
+
+impl Progress {
+    fn new(label: String, total: u64) -> Self {
+        Self {
+            label,
+            total,
+            done: 0,
         }
-        bytes
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.done += bytes as u64;
+        let percent = (self.done * 100).checked_div(self.total).unwrap_or(100);
+        eprint!(
+            "\r[FILE] {}: {}/{} bytes ({}%)",
+            self.label, self.done, self.total, percent
+        );
+    }
+
+    fn finish(&self) {
+        eprintln!();
     }
 }
 
-fn xor_cipher(data: &[u8], keystream: &mut KeystreamGenerator) -> Vec<u8> {
-    data.iter().map(|&b| b ^ keystream.next_byte()).collect()
+/// A file being received, written straight to disk as chunks arrive.
+struct IncomingFile {
+    path: PathBuf,
+    file: File,
+    expected: u64,
+    progress: Progress,
 }
 
-fn diffie_hellman_exchange(stream: &mut TcpStream, is_server: bool) -> io::Result<u64> {
-    println!("\n[DH] Starting key exchange...");
-    println!("[DH] Using hardcoded DH parameters:");
-    println!("p = {:016X} (64-bit prime - public)", P);
-    println!("g = {} (generator - public)", G);
+/// Keep only the final component of a name sent by the peer, so a transfer
+/// can never write outside the download directory.
+fn safe_file_name(raw: &str) -> Option<String> {
+    let name = Path::new(raw).file_name()?.to_str()?;
+    if name.chars().any(char::is_control) {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// `dir/name`, or `dir/stem (n).ext` if that file already exists.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let stem = Path::new(name)
+        .file_stem()
+        .map_or(name.into(), |s| s.to_string_lossy());
+    let ext = Path::new(name)
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| !path.exists())
+        .expect("some numbered name is free")
+}
+
+impl IncomingFile {
+    fn start(body: &[u8], download_dir: &Path) -> Result<Self, String> {
+        if body.len() < 8 {
+            return Err("malformed file header".to_string());
+        }
+        let expected = u64::from_be_bytes(body[..8].try_into().unwrap());
+        let name =
+            safe_file_name(&String::from_utf8_lossy(&body[8..])).ok_or("invalid file name")?;
+        fs::create_dir_all(download_dir)
+            .map_err(|e| format!("{}: {}", download_dir.display(), e))?;
+        let path = unique_path(download_dir, &name);
+        let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Self {
+            path,
+            file,
+            expected,
+            progress: Progress::new(name, expected),
+        })
+    }
+
+    fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if self.progress.done + chunk.len() as u64 > self.expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("more than the announced {} bytes", self.expected),
+            ));
+        }
+        self.file.write_all(chunk)?;
+        self.progress.advance(chunk.len());
+        Ok(())
+    }
+}
+
+/// The path after "/send", or None when the line is not a send command.
+fn parse_send(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix(SEND_COMMAND)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+/// Queue `path` for the peer as a FileStart, its chunks, then a FileEnd.
+fn send_file(queue: impl Fn(MessageKind, &[u8]) -> io::Result<()>, path: &Path) -> io::Result<()> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file name"))?;
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut header = size.to_be_bytes().to_vec();
+    header.extend_from_slice(name.as_bytes());
+    queue(MessageKind::FileStart, &header)?;
+
+    let mut chunk = vec![0u8; FILE_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        queue(MessageKind::FileChunk, &chunk[..n])?;
+    }
+    queue(MessageKind::FileEnd, &[])
+}
+
+fn print_fingerprint(shared_secret: &[u8]) {
+    println!("[VERIFY] Fingerprint: {}", fingerprint(shared_secret));
+    println!("Compare it with your peer out-of-band; a mismatch means someone is in the middle.");
+}
+
+/// The LCG state repeats after at most m steps; warn once a key has produced
+/// half that many keystream bytes.
+fn lcg_keystream_limit(cipher: CipherKind, lcg: LcgParams) -> Option<u64> {
+    match cipher {
+        CipherKind::LcgInsecure => Some(lcg.m / 2),
+        CipherKind::Chacha20 | CipherKind::AesCtr => None,
+    }
+}
+
+/// One direction of the conversation. Every message gets a fresh keystream
+/// derived from this key and the message's nonce.
+struct Direction {
+    cipher: CipherKind,
+    lcg: LcgParams,
+    key: [u8; 32],
+    nonce_prefix: [u8; 4],
+    next_seq: u64,
+    /// Highest sequence number accepted so far (receiving side).
+    last_seen_seq: Option<u64>,
+    /// Messages sent under each key before it is ratcheted.
+    rekey_every: u64,
+    /// Number of ratchet steps applied to `key`.
+    epoch: u64,
+    /// Keystream bytes used under the current key.
+    keystream_used: u64,
+    /// Usage at which the keystream is close to repeating, for ciphers whose
+    /// period is within reach of a long session.
+    keystream_limit: Option<u64>,
+    keystream_warned: bool,
+}
+
+impl Direction {
+    fn new(cipher: CipherKind, shared_secret: &[u8], label: &str) -> Self {
+        let key = derive_key(shared_secret, label);
+        debug_println!("[KDF] stream key = SHA-256(\"{}\" || secret)", label);
+        debug_println!("= {}", hex::encode(key));
+        let lcg = LcgParams::default();
+        Self {
+            cipher,
+            lcg,
+            key,
+            nonce_prefix: OsRng.next_u32().to_be_bytes(),
+            next_seq: 0,
+            last_seen_seq: None,
+            rekey_every: DEFAULT_REKEY_EVERY,
+            epoch: 0,
+            keystream_used: 0,
+            keystream_limit: lcg_keystream_limit(cipher, lcg),
+            keystream_warned: false,
+        }
+    }
+
+    fn with_lcg(mut self, lcg: LcgParams) -> Self {
+        self.lcg = lcg;
+        self.keystream_limit = lcg_keystream_limit(self.cipher, lcg);
+        self
+    }
+
+    fn with_rekey_every(mut self, rekey_every: u64) -> Self {
+        self.rekey_every = rekey_every;
+        self
+    }
+
+    /// Ratchet the key forward to the epoch of `seq`. Both peers derive the
+    /// epoch from the authenticated sequence number, so they switch keys on
+    /// the same message even if some messages were dropped in between. Old
+    /// keys cannot be recovered from new ones.
+    fn advance_to(&mut self, seq: u64) {
+        let epoch = seq / self.rekey_every;
+        while self.epoch < epoch {
+            self.key = derive_key(&self.key, REKEY_LABEL);
+            self.epoch += 1;
+            self.keystream_used = 0;
+            self.keystream_warned = false;
+            debug_println!(
+                "\n[REKEY] Epoch {}: key = SHA-256(\"{}\" || previous key)",
+                self.epoch,
+                REKEY_LABEL
+            );
+        }
+    }
+
+    fn nonce(&self, seq: u64) -> [u8; NONCE_LEN] {
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..4].copy_from_slice(&self.nonce_prefix);
+        nonce[4..].copy_from_slice(&seq.to_be_bytes());
+        nonce
+    }
+
+    /// Nonce for the next outgoing message.
+    fn next_nonce(&mut self) -> [u8; NONCE_LEN] {
+        self.advance_to(self.next_seq);
+        let nonce = self.nonce(self.next_seq);
+        self.next_seq += 1;
+        nonce
+    }
+
+    /// Accept `seq` only if it is newer than every message seen so far.
+    fn check_sequence(&mut self, seq: u64) -> Result<(), String> {
+        if let Some(last) = self.last_seen_seq {
+            if seq <= last {
+                return Err(format!(
+                    "replayed or stale message (seq {}, last seen {})",
+                    seq, last
+                ));
+            }
+        }
+        self.last_seen_seq = Some(seq);
+        self.advance_to(seq);
+        Ok(())
+    }
+
+    /// Count `bytes` of keystream against the current key. Returns true, and
+    /// prints a warning, the first time usage reaches the cipher's limit.
+    fn consume_keystream(&mut self, bytes: usize) -> bool {
+        self.keystream_used += bytes as u64;
+        match self.keystream_limit {
+            Some(limit) if !self.keystream_warned && self.keystream_used >= limit => {
+                self.keystream_warned = true;
+                println!(
+                    "\n[WARN] {} keystream bytes used under one key; the LCG is close to \
+                     repeating its output. Use --rekey-every or a real cipher.",
+                    self.keystream_used
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Cipher for the message sent under `nonce`.
+    fn cipher(&self, nonce: &[u8; NONCE_LEN]) -> Box<dyn StreamCipher> {
+        Box::new(XorCipher::new(self.keystream(nonce)))
+    }
+
+    /// Keystream for the message sent under `nonce`.
+    fn keystream(&self, nonce: &[u8; NONCE_LEN]) -> Box<dyn Keystream> {
+        match self.cipher {
+            CipherKind::Chacha20 => Box::new(ChaChaKeystream::new(self.key, *nonce)),
+            CipherKind::AesCtr => Box::new(AesCtrKeystream::new(self.key, *nonce)),
+            CipherKind::LcgInsecure => {
+                let mut hasher = Sha256::new();
+                hasher.update(self.key);
+                hasher.update(nonce);
+                let digest = hasher.finalize();
+                let seed = u64::from_be_bytes(digest[..8].try_into().unwrap());
+                Box::new(KeystreamGenerator::with_params(seed, self.lcg))
+            }
+        }
+    }
+}
+
+fn print_cipher_info(cipher: CipherKind, lcg: LcgParams) {
+    debug_println!("[STREAM] Generating keystream from secret...");
+    match cipher {
+        CipherKind::Chacha20 => {
+            debug_println!("Algorithm: ChaCha20 (key = KDF(secret), nonce = per message)")
+        }
+        CipherKind::AesCtr => {
+            debug_println!("Algorithm: AES-256-CTR (key = KDF(secret), counter = nonce || block)")
+        }
+        CipherKind::LcgInsecure => {
+            debug_println!("Algorithm: LCG (a={}, c={}, m={})", lcg.a, lcg.c, lcg.m);
+            debug_println!("Seed: SHA-256(key || nonce)[0..8], per message");
+        }
+    }
+}
+
+fn print_keystream_preview(keystream: &dyn Keystream) {
+    let preview = keystream.peek_bytes(20);
+    debug_print!("\nKeystream: ");
+    for (i, &b) in preview.iter().enumerate() {
+        debug_print!("{:02X} ", b);
+        if i >= 11 {
+            debug_print!("...");
+            break;
+        }
+    }
+    debug_println!("\n");
+}
+
+fn diffie_hellman_exchange(stream: &mut TcpStream, is_server: bool) -> io::Result<Vec<u8>> {
+    debug_println!("\n[DH] Starting key exchange...");
+    debug_println!("[DH] Using hardcoded DH parameters:");
+    debug_println!("p = {:016X} (64-bit prime - public)", P);
+    debug_println!("g = {} (generator - public)", G);
 
     let private_key = generate_random();
-    println!("\n[DH] Generating our keypair...");
-    println!("private_key = {:016X} (random 64-bit)", private_key);
+    debug_println!("\n[DH] Generating our keypair...");
+    debug_println!("private_key = {:016X} (random 64-bit)", private_key);
 
-    let public_key = mod_exp(G, private_key, P);
-    println!("public_key = g^private mod p");
-    println!("= {}^{} mod p", G, private_key);
-    println!("= {:016X}", public_key);
+    let public_key = dh_public_key(private_key);
+    debug_println!("public_key = g^private mod p");
+    debug_println!("= {}^{} mod p", G, private_key);
+    debug_println!("= {:016X}", public_key);
 
-    println!("\n[DH] Exchanging keys...");
+    debug_println!("\n[DH] Exchanging keys...");
 
     let their_public = if is_server {
-        println!("[NETWORK] Receiving public key (8 bytes)...");
+        debug_println!("[NETWORK] Receiving public key (8 bytes)...");
         let mut buf = [0u8; 8];
         stream.read_exact(&mut buf)?;
         let their_key = u64::from_be_bytes(buf);
-        println!("← Receive their public: {:016X}", their_key);
+        debug_println!("← Receive their public: {:016X}", their_key);
 
-        println!("[NETWORK] Sending public key (8 bytes)...");
+        debug_println!("[NETWORK] Sending public key (8 bytes)...");
         stream.write_all(&public_key.to_be_bytes())?;
         stream.flush()?;
-        println!("→ Send our public: {:016X}", public_key);
+        debug_println!("→ Send our public: {:016X}", public_key);
 
         their_key
     } else {
-        println!("[NETWORK] Sending public key (8 bytes)...");
+        debug_println!("[NETWORK] Sending public key (8 bytes)...");
         stream.write_all(&public_key.to_be_bytes())?;
         stream.flush()?;
-        println!("→ Send our public: {:016X}", public_key);
+        debug_println!("→ Send our public: {:016X}", public_key);
 
-        println!("[NETWORK] Received public key (8 bytes) ✓");
+        debug_println!("[NETWORK] Received public key (8 bytes) ✓");
         let mut buf = [0u8; 8];
         stream.read_exact(&mut buf)?;
         let their_key = u64::from_be_bytes(buf);
-        println!("← Receive their public: {:016X}", their_key);
+        debug_println!("← Receive their public: {:016X}", their_key);
 
         their_key
     };
 
-    println!("\n[DH] Computing shared secret...");
-    println!("Formula: secret = (their_public)^(our_private) mod p");
-    println!();
-    let shared_secret = mod_exp(their_public, private_key, P);
-    println!(
+    debug_println!("\n[DH] Computing shared secret...");
+    debug_println!("Formula: secret = (their_public)^(our_private) mod p");
+    debug_println!();
+    let shared_secret = dh_shared_secret(their_public, private_key);
+    debug_println!(
         "secret = ({:016X})^({:016X}) mod p",
-        their_public, private_key
+        their_public,
+        private_key
     );
-    println!("= {:016X}", shared_secret);
+    debug_println!("= {:016X}", shared_secret);
 
-    println!("\n[VERIFY] Both sides computed the same secret ✓");
+    debug_println!("\n[VERIFY] Both sides computed the same secret ✓");
+    let shared_secret = shared_secret.to_be_bytes().to_vec();
+    print_fingerprint(&shared_secret);
 
     Ok(shared_secret)
 }
 
-fn run_server(port: u16) -> io::Result<()> {
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", port))?;
-    println!("[SERVER] Listening on 0.0.0.0:{}", port);
-    println!();
-    println!("[DH] Diffie-Hellman Parameters:");
-    println!("p = {:016X}", P);
-    println!("g = {}", G);
-    println!();
+fn write_length_prefixed(stream: &mut TcpStream, bytes: &[u8]) -> io::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_be_bytes())?;
+    stream.write_all(bytes)?;
+    stream.flush()
+}
+
+fn read_length_prefixed(stream: &mut TcpStream, max_len: usize) -> io::Result<Vec<u8>> {
+    let mut len_buf = [0u8; 4];
+    stream.read_exact(&mut len_buf)?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    if len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("public key too large ({} bytes)", len),
+        ));
+    }
+    let mut buf = vec![0u8; len];
+    stream.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Same exchange as `diffie_hellman_exchange`, over the 2048-bit MODP group.
+/// Public keys travel as a 4-byte big-endian length followed by the value.
+fn diffie_hellman_exchange_strong(stream: &mut TcpStream, is_server: bool) -> io::Result<Vec<u8>> {
+    let p = modp_2048_prime();
+    let g = BigUint::from(G);
+
+    debug_println!("\n[DH] Starting key exchange...");
+    debug_println!("[DH] Using RFC 3526 group 14:");
+    debug_println!("p = {}... (2048-bit prime - public)", &MODP_2048_P[..16]);
+    debug_println!("g = {} (generator - public)", G);
+
+    let mut private_bytes = [0u8; STRONG_PRIVATE_BYTES];
+    OsRng.fill_bytes(&mut private_bytes);
+    let private_key = BigUint::from_bytes_be(&private_bytes);
+    debug_println!("\n[DH] Generating our keypair...");
+    debug_println!(
+        "private_key = {}... (random {}-bit)",
+        &hex::encode(private_bytes)[..16],
+        STRONG_PRIVATE_BYTES * 8
+    );
+
+    let public_key = to_group_bytes(&mod_exp_big(&g, &private_key, &p));
+    debug_println!("public_key = g^private mod p");
+    debug_println!("= {}...", &hex::encode(&public_key)[..16]);
+
+    debug_println!("\n[DH] Exchanging keys...");
+
+    let their_bytes = if is_server {
+        let their_bytes = read_length_prefixed(stream, MODP_2048_BYTES)?;
+        debug_println!("← Receive their public ({} bytes)", their_bytes.len());
+        write_length_prefixed(stream, &public_key)?;
+        debug_println!("→ Send our public ({} bytes)", public_key.len());
+        their_bytes
+    } else {
+        write_length_prefixed(stream, &public_key)?;
+        debug_println!("→ Send our public ({} bytes)", public_key.len());
+        let their_bytes = read_length_prefixed(stream, MODP_2048_BYTES)?;
+        debug_println!("← Receive their public ({} bytes)", their_bytes.len());
+        their_bytes
+    };
+
+    let their_public = BigUint::from_bytes_be(&their_bytes);
+    let one = BigUint::from(1u8);
+    if their_public <= one || their_public >= &p - &one {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "peer sent an invalid DH public key",
+        ));
+    }
+
+    debug_println!("\n[DH] Computing shared secret...");
+    debug_println!("Formula: secret = (their_public)^(our_private) mod p");
+    let shared_secret = to_group_bytes(&mod_exp_big(&their_public, &private_key, &p));
+    debug_println!("= {}...", &hex::encode(&shared_secret)[..16]);
+
+    debug_println!("\n[VERIFY] Both sides computed the same secret ✓");
+    print_fingerprint(&shared_secret);
+
+    Ok(shared_secret)
+}
+
+fn key_exchange(stream: &mut TcpStream, is_server: bool, strong_dh: bool) -> io::Result<Vec<u8>> {
+    let result = if strong_dh {
+        diffie_hellman_exchange_strong(stream, is_server)
+    } else {
+        diffie_hellman_exchange(stream, is_server)
+    };
+    result.map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset => io::Error::new(
+            e.kind(),
+            "peer disconnected during key exchange (is it using the same --strong-dh setting?)",
+        ),
+        _ => e,
+    })
+}
+
+/// Everything besides the DH group that both peers must agree on, sent
+/// (authenticated) right after the key exchange so a mismatch fails with a
+/// clear message instead of decrypting to garbage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Settings {
+    version: u8,
+    cipher: CipherKind,
+    framing: Framing,
+    rekey_every: u64,
+    lcg: LcgParams,
+}
+
+impl Settings {
+    fn new(opts: &ChatOptions) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            cipher: opts.cipher,
+            framing: opts.framing,
+            rekey_every: opts.rekey_every,
+            lcg: opts.lcg,
+        }
+    }
+
+    fn to_bytes(self) -> [u8; SETTINGS_LEN] {
+        let mut bytes = [0u8; SETTINGS_LEN];
+        bytes[0] = self.version;
+        bytes[1] = match self.cipher {
+            CipherKind::Chacha20 => 0,
+            CipherKind::AesCtr => 1,
+            CipherKind::LcgInsecure => 2,
+        };
+        bytes[2] = match self.framing {
+            Framing::Lines(Encoding::Hex) => 0,
+            Framing::Lines(Encoding::Base64) => 1,
+            Framing::Binary => 2,
+        };
+        bytes[3..11].copy_from_slice(&self.rekey_every.to_be_bytes());
+        bytes[11..19].copy_from_slice(&self.lcg.a.to_be_bytes());
+        bytes[19..27].copy_from_slice(&self.lcg.c.to_be_bytes());
+        bytes[27..35].copy_from_slice(&self.lcg.m.to_be_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; SETTINGS_LEN]) -> Result<Self, String> {
+        if bytes[0] != PROTOCOL_VERSION {
+            return Err(format!(
+                "peer speaks protocol version {}, we speak {}",
+                bytes[0], PROTOCOL_VERSION
+            ));
+        }
+        let word = |at: usize| u64::from_be_bytes(bytes[at..at + 8].try_into().unwrap());
+        Ok(Self {
+            version: bytes[0],
+            cipher: match bytes[1] {
+                0 => CipherKind::Chacha20,
+                1 => CipherKind::AesCtr,
+                2 => CipherKind::LcgInsecure,
+                other => return Err(format!("peer uses an unknown cipher ({})", other)),
+            },
+            framing: match bytes[2] {
+                0 => Framing::Lines(Encoding::Hex),
+                1 => Framing::Lines(Encoding::Base64),
+                2 => Framing::Binary,
+                other => return Err(format!("peer uses an unknown framing ({})", other)),
+            },
+            rekey_every: word(3),
+            lcg: LcgParams {
+                a: word(11),
+                c: word(19),
+                m: word(27),
+            },
+        })
+    }
+
+    /// One line per setting the peer does not share, naming the flag to fix.
+    fn mismatches(&self, peer: &Settings) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.cipher != peer.cipher {
+            problems.push(format!(
+                "--cipher: we use {}, peer uses {}",
+                cipher_name(self.cipher),
+                cipher_name(peer.cipher)
+            ));
+        }
+        if self.framing != peer.framing {
+            problems.push(format!(
+                "framing: we use {}, peer uses {}",
+                framing_name(self.framing),
+                framing_name(peer.framing)
+            ));
+        }
+        if self.rekey_every != peer.rekey_every {
+            problems.push(format!(
+                "--rekey-every: we use {}, peer uses {}",
+                self.rekey_every, peer.rekey_every
+            ));
+        }
+        if self.cipher == CipherKind::LcgInsecure && self.lcg != peer.lcg {
+            problems.push(format!(
+                "--lcg-a/--lcg-c/--lcg-m: we use {}/{}/{}, peer uses {}/{}/{}",
+                self.lcg.a, self.lcg.c, self.lcg.m, peer.lcg.a, peer.lcg.c, peer.lcg.m
+            ));
+        }
+        problems
+    }
+}
+
+fn cipher_name(cipher: CipherKind) -> String {
+    cipher
+        .to_possible_value()
+        .map_or_else(|| format!("{:?}", cipher), |v| v.get_name().to_string())
+}
+
+fn framing_name(framing: Framing) -> &'static str {
+    match framing {
+        Framing::Lines(Encoding::Hex) => "hex lines",
+        Framing::Lines(Encoding::Base64) => "base64 lines (--encoding base64)",
+        Framing::Binary => "binary frames (--binary-frames)",
+    }
+}
+
+/// Swap `Settings` with the peer, each side's message authenticated with
+/// the session MAC key, and fail unless they match.
+fn negotiate(stream: &mut TcpStream, shared_secret: &[u8], opts: &ChatOptions) -> io::Result<()> {
+    let mac_key = derive_key(shared_secret, MAC_KEY_LABEL);
+    let ours = Settings::new(opts);
+    let bytes = ours.to_bytes();
+    debug_println!(
+        "\n[NEGOTIATE] Sending our settings (protocol v{}): cipher={}, framing={}, rekey-every={}",
+        PROTOCOL_VERSION,
+        cipher_name(ours.cipher),
+        framing_name(ours.framing),
+        ours.rekey_every
+    );
+    stream.write_all(&bytes)?;
+    stream.write_all(&compute_mac(&mac_key, &bytes))?;
+    stream.flush()?;
+
+    let mut theirs = [0u8; SETTINGS_LEN];
+    let mut tag = [0u8; MAC_LEN];
+    stream.read_exact(&mut theirs)?;
+    stream.read_exact(&mut tag)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if !verify_mac(&mac_key, &theirs, &tag) {
+        return Err(invalid(
+            "peer's settings failed authentication (tampered, or an incompatible version)"
+                .to_string(),
+        ));
+    }
+    let peer = Settings::from_bytes(&theirs).map_err(invalid)?;
+    let problems = ours.mismatches(&peer);
+    if !problems.is_empty() {
+        return Err(invalid(format!(
+            "peer settings differ, both sides must match:\n  {}",
+            problems.join("\n  ")
+        )));
+    }
+    debug_println!("[NEGOTIATE] Peer settings match ✓");
+    Ok(())
+}
+
+/// Key exchange followed by settings negotiation.
+fn handshake(stream: &mut TcpStream, is_server: bool, opts: &ChatOptions) -> io::Result<Vec<u8>> {
+    let shared_secret = key_exchange(stream, is_server, opts.strong_dh)?;
+    negotiate(stream, &shared_secret, opts).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset => {
+            io::Error::new(e.kind(), "peer disconnected during settings negotiation")
+        }
+        _ => e,
+    })?;
+    Ok(shared_secret)
+}
+
+fn run_server(bind: IpAddr, port: u16, broadcast: bool, opts: ChatOptions) -> io::Result<()> {
+    let listener = TcpListener::bind((bind, port))?;
+    println!("[SERVER] Listening on {}", listener.local_addr()?);
+    debug_println!();
+    debug_println!("[DH] Diffie-Hellman Parameters:");
+    if opts.strong_dh {
+        debug_println!("p = RFC 3526 group 14 (2048-bit)");
+    } else {
+        debug_println!("p = {:016X}", P);
+    }
+    debug_println!("g = {}", G);
+    debug_println!();
+
+    if broadcast {
+        return run_broadcast_server(listener, opts);
+    }
+
     println!("[SERVER] Waiting for client...");
 
     let (mut stream, addr) = listener.accept()?;
     println!("\n[CLIENT] Connected from {}", addr);
 
-    let shared_secret = diffie_hellman_exchange(&mut stream, true)?;
-    let mut keystream = KeystreamGenerator::new(shared_secret);
+    let shared_secret = handshake(&mut stream, true, &opts)?;
+    chat_session(stream, &shared_secret, true, &opts)
+}
 
-    let preview = keystream.peek_bytes(20);
-    print!("\nKeystream: ");
-    for (i, &b) in preview.iter().enumerate() {
-        print!("{:02X} ", b);
-        if i >= 11 {
-            print!("...");
-            break;
+/// A connected broadcast client: the queue feeding its writer thread, which
+/// encrypts with that client's own key.
+struct ClientHandle {
+    /// Chat handle, "#<id>" until the client's hello arrives. Unique among
+    /// connected clients so whispers reach one person.
+    handle: String,
+    sender: mpsc::SyncSender<Outgoing>,
+    writer: thread::JoinHandle<()>,
+}
+
+/// Connected clients, keyed by connection id.
+type ClientList = Arc<Mutex<HashMap<u64, ClientHandle>>>;
+
+/// A chat's writer queue and thread, shared with the Ctrl-C handler so
+/// whichever side ends the chat first can shut the writer down.
+type WriterSlot = Arc<Mutex<Option<(mpsc::SyncSender<Outgoing>, thread::JoinHandle<()>)>>>;
+
+fn on_interrupt(handler: impl FnMut() + Send + 'static) -> io::Result<()> {
+    ctrlc::set_handler(handler).map_err(|e| io::Error::other(format!("Ctrl-C handler: {}", e)))
+}
+
+/// Send every client a leave notice and wait until each writer has
+/// delivered it.
+fn disconnect_all(clients: &ClientList) {
+    broadcast_message(clients, None, MessageKind::Leave, "");
+    let handles: Vec<ClientHandle> = clients
+        .lock()
+        .unwrap()
+        .drain()
+        .map(|(_, client)| client)
+        .collect();
+    for client in handles {
+        drop(client.sender);
+        let _ = client.writer.join();
+    }
+}
+
+/// Queue `message` for every client except `from`.
+fn broadcast_message(clients: &ClientList, from: Option<u64>, kind: MessageKind, message: &str) {
+    let clients = clients.lock().unwrap();
+    for (&id, client) in clients.iter() {
+        if Some(id) != from {
+            // A closed queue means that client is on its way out; skip it.
+            let _ = client.sender.send((kind, message.as_bytes().to_vec()));
         }
     }
-    println!("\n");
+}
 
-    println!("✓ Secure channel established!\n");
+/// Split "/whisper <handle> <message>" into its handle and message.
+fn parse_whisper(line: &str) -> Option<Result<(&str, &str), String>> {
+    let rest = line.trim_start().strip_prefix(WHISPER_COMMAND)?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let usage = format!("usage: {} <handle> <message>", WHISPER_COMMAND);
+    Some(match rest.trim_start().split_once(char::is_whitespace) {
+        Some((handle, message)) if !message.trim().is_empty() => Ok((handle, message.trim())),
+        _ => Err(usage),
+    })
+}
 
-    let mut reader = BufReader::new(stream.try_clone()?);
+/// Queue `message` for the client named `handle` only. Returns false if
+/// nobody uses that handle.
+fn whisper_message(clients: &ClientList, from: &str, handle: &str, message: &str) -> bool {
+    let clients = clients.lock().unwrap();
+    let Some(client) = clients.values().find(|client| client.handle == handle) else {
+        return false;
+    };
+    let text = format!("{} (whisper): {}", from, message);
+    let _ = client
+        .sender
+        .send((MessageKind::Text, text.as_bytes().to_vec()));
+    true
+}
 
-    loop {
-        // Check for incoming messages (non-blocking attempt)
-        let mut line = String::new();
-        if reader.read_line(&mut line)? == 0 {
-            break;
-        }
+/// Give client `id` the handle it asked for, or "<handle>#2", "<handle>#3",
+/// ... when another client already uses it.
+fn claim_handle(clients: &ClientList, id: u64, wanted: String) -> String {
+    let mut clients = clients.lock().unwrap();
+    let taken = |handle: &str| {
+        clients
+            .iter()
+            .any(|(&other, client)| other != id && client.handle == handle)
+    };
+    let handle = iter::once(wanted.clone())
+        .chain((2..).map(|n| format!("{}#{}", wanted, n)))
+        .find(|handle| !taken(handle))
+        .expect("some numbered handle is free");
+    if let Some(client) = clients.get_mut(&id) {
+        client.handle = handle.clone();
+    }
+    handle
+}
 
-        if !line.trim().is_empty() {
-            let encrypted = hex::decode(line.trim()).unwrap_or_default();
-            if !encrypted.is_empty() {
-                println!(
-                    "\n[NETWORK] Received encrypted message ({} bytes)",
-                    encrypted.len()
-                );
-                println!("[-] Received {} bytes", encrypted.len());
+/// Queue `message` for a single client, e.g. an error about its own command.
+fn reply_to(clients: &ClientList, id: u64, message: &str) {
+    if let Some(client) = clients.lock().unwrap().get(&id) {
+        let _ = client
+            .sender
+            .send((MessageKind::Text, message.as_bytes().to_vec()));
+    }
+}
 
-                println!("\n[DECRYPT]");
-                print!("Cipher: ");
-                for &b in encrypted.iter().take(3) {
-                    print!("{:02x} ", b);
+fn run_broadcast_server(listener: TcpListener, opts: ChatOptions) -> io::Result<()> {
+    let clients: ClientList = Arc::default();
+    println!("[SERVER] Broadcast mode: waiting for clients...");
+
+    let accept_clients = Arc::clone(&clients);
+    let accept_opts = opts.clone();
+    let acceptor = thread::spawn(move || {
+        for (id, connection) in (0u64..).zip(listener.incoming()) {
+            let stream = match connection {
+                Ok(stream) => stream,
+                Err(e) => {
+                    println!("[WARN] Failed to accept connection: {}", e);
+                    continue;
                 }
-                println!();
-
-                let position = (keystream.state as usize) % (LCG_M as usize);
-                let key_bytes: Vec<u8> = encrypted
-                    .iter()
-                    .take(3)
-                    .enumerate()
-                    .map(|(i, _)| {
-                        let mut temp = keystream.state;
-                        for _ in 0..i {
-                            temp = ((temp as u128 * LCG_A as u128 + LCG_C as u128) % LCG_M as u128)
-                                as u64;
-                        }
-                        (temp & 0xFF) as u8
-                    })
-                    .collect();
-
-                print!("Key: ");
-                for (i, &b) in key_bytes.iter().enumerate() {
-                    print!("{:02x} ", b);
-                    if i >= 2 {
-                        break;
-                    }
+            };
+            let clients = Arc::clone(&accept_clients);
+            let opts = accept_opts.clone();
+            thread::spawn(move || {
+                if let Err(e) = serve_client(id, stream, &opts, &clients) {
+                    println!("\n[WARN] Client #{}: {}", id, e);
                 }
-                println!(" (keystream position: {})", position);
+                clients.lock().unwrap().remove(&id);
+                println!("\n[SERVER] Client #{} disconnected", id);
+            });
+        }
+    });
 
-                let decrypted = xor_cipher(&encrypted, &mut keystream);
-                let message = String::from_utf8_lossy(&decrypted);
-                print!("Plain: ");
-                for &b in decrypted.iter().take(3) {
-                    print!("{:02x} ", b);
-                }
-                println!("→ {:?}", message);
+    // Ctrl-C behaves like /quit.
+    let interrupt_clients = Arc::clone(&clients);
+    let interrupt_opts = opts.clone();
+    on_interrupt(move || {
+        disconnect_all(&interrupt_clients);
+        interrupt_opts.flush_log();
+        println!("\n[INFO] Interrupted, server closed");
+        process::exit(0);
+    })?;
 
-                println!(
-                    "\n[TEST] Round-trip verified: {:?} → encrypt → decrypt → {:?} ✓",
-                    message, message
-                );
-                println!("\n[CLIENT] {}", message);
+    // Lines typed on the server console go to every client.
+    let mut lines = io::stdin().lock().lines();
+    while let Some(line) = next_message(&mut lines) {
+        let line = line?;
+        if line.trim() == QUIT_COMMAND {
+            disconnect_all(&clients);
+            println!("\n[INFO] Server closed");
+            return Ok(());
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        opts.echo_sent(&line);
+        opts.log("sent", &opts.name, &line);
+        match parse_whisper(&line) {
+            Some(Ok((handle, message))) => {
+                if !whisper_message(&clients, &opts.name, handle, message) {
+                    println!("[WARN] No client named '{}'", handle);
+                }
             }
+            Some(Err(usage)) => println!("[WARN] {}", usage),
+            None => broadcast_message(&clients, None, MessageKind::Text, &line),
         }
     }
 
+    // Console closed: keep relaying between clients.
+    let _ = acceptor.join();
     Ok(())
 }
 
-fn run_client(address: String) -> io::Result<()> {
-    let mut stream = TcpStream::connect(&address)?;
-    println!("[CLIENT] Connected to {}", address);
+/// Handle one broadcast client: key exchange, then relay everything it sends.
+fn serve_client(
+    id: u64,
+    mut stream: TcpStream,
+    opts: &ChatOptions,
+    clients: &ClientList,
+) -> io::Result<()> {
+    println!("\n[CLIENT #{}] Connected from {}", id, stream.peer_addr()?);
 
-    let shared_secret = diffie_hellman_exchange(&mut stream, false)?;
-    let mut keystream = KeystreamGenerator::new(shared_secret);
+    let shared_secret = handshake(&mut stream, true, opts)?;
+    let outgoing = opts.direction(&shared_secret, SERVER_TO_CLIENT_LABEL);
+    let mut incoming = opts.direction(&shared_secret, CLIENT_TO_SERVER_LABEL);
+    let mac_key = derive_key(&shared_secret, MAC_KEY_LABEL);
 
-    let preview = keystream.peek_bytes(20);
-    print!("\nKeystream: ");
-    for (i, &b) in preview.iter().enumerate() {
-        print!("{:02X} ", b);
-        if i >= 11 {
-            print!("...");
-            break;
+    // The writer thread ends once this client's queue is dropped from the
+    // list or after it delivers a leave notice.
+    let (sender, writer) = spawn_writer(stream.try_clone()?, outgoing, mac_key, opts.framing);
+    sender
+        .send((MessageKind::Hello, opts.name.clone().into_bytes()))
+        .expect("queue is still open");
+    stream.set_read_timeout(opts.timeout)?;
+    // Until its hello arrives the client is known by its connection id.
+    let mut handle = format!("#{}", id);
+    clients.lock().unwrap().insert(
+        id,
+        ClientHandle {
+            handle: handle.clone(),
+            sender,
+            writer,
+        },
+    );
+    println!("✓ Secure channel established with client #{}!", id);
+
+    receive_loop(stream, &mut incoming, &mac_key, opts, |event| match event {
+        ChatEvent::Hello(message) => {
+            let wanted = sanitize_handle(message);
+            handle = claim_handle(clients, id, wanted.clone());
+            if handle != wanted {
+                let notice = format!("Handle '{}' is taken, you are '{}'", wanted, handle);
+                reply_to(clients, id, &notice);
+            }
+            println!("\n[CLIENT #{}] Handle: {}", id, handle);
+            let notice = format!("{} joined the chat", handle);
+            broadcast_message(clients, Some(id), MessageKind::Text, &notice);
         }
+        ChatEvent::Text(message) => {
+            println!("\n{}[{}] {}", opts.stamp(), handle, indent_lines(message));
+            opts.log("received", &handle, message);
+            match parse_whisper(message) {
+                Some(Ok((target, text))) => {
+                    if !whisper_message(clients, &handle, target, text) {
+                        reply_to(clients, id, &format!("No client named '{}'", target));
+                    }
+                }
+                Some(Err(usage)) => reply_to(clients, id, &usage),
+                None => {
+                    let relayed = format!("{}: {}", handle, message);
+                    broadcast_message(clients, Some(id), MessageKind::Text, &relayed);
+                }
+            }
+        }
+        ChatEvent::Leave => {
+            println!("\n[{}] left the chat", handle);
+            let notice = format!("{} left the chat", handle);
+            broadcast_message(clients, Some(id), MessageKind::Text, &notice);
+        }
+    })
+}
+
+/// Resolve `host:port`, `a.b.c.d:port` or `[v6]:port` to every address it
+/// names (hostnames may yield both A and AAAA records).
+fn resolve_address(address: &str) -> io::Result<Vec<SocketAddr>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+    if address.parse::<IpAddr>().is_ok() {
+        return Err(invalid(format!(
+            "missing port in '{}' (IPv6 needs brackets: [addr]:port)",
+            address
+        )));
+    }
+    if !address.starts_with('[') && address.matches(':').count() > 1 {
+        return Err(invalid(format!(
+            "IPv6 addresses must be bracketed, e.g. [{}]",
+            address
+        )));
+    }
+
+    let addrs: Vec<SocketAddr> = address
+        .to_socket_addrs()
+        .map_err(|e| invalid(format!("cannot resolve '{}': {}", address, e)))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(invalid(format!("'{}' has no addresses", address)));
     }
-    println!("\n");
+    Ok(addrs)
+}
+
+/// Try each resolved address in turn, IPv6 and IPv4 alike.
+fn connect_any(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = None;
+    for addr in resolve_address(address)? {
+        match TcpStream::connect(addr) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => {
+                debug_println!("[NETWORK] {} unreachable: {}", addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    let error = last_error.expect("at least one address was tried");
+    if error.kind() == io::ErrorKind::ConnectionRefused {
+        return Err(io::Error::new(
+            error.kind(),
+            format!(
+                "connection refused by {}: nothing is listening there. \
+                 Start the other side with `streamchat server <PORT>` first",
+                address
+            ),
+        ));
+    }
+    Err(error)
+}
+
+fn run_client(address: String, opts: ChatOptions) -> io::Result<()> {
+    let mut stream = connect_any(&address)?;
+    println!("[CLIENT] Connected to {}", stream.peer_addr()?);
+
+    let shared_secret = handshake(&mut stream, false, &opts)?;
+    chat_session(stream, &shared_secret, false, &opts)
+}
+
+/// Run an established chat: a background thread prints what the peer sends
+/// while this thread encrypts and sends stdin lines.
+fn chat_session(
+    stream: TcpStream,
+    shared_secret: &[u8],
+    is_server: bool,
+    opts: &ChatOptions,
+) -> io::Result<()> {
+    let (send_label, recv_label, peer_label) = if is_server {
+        (SERVER_TO_CLIENT_LABEL, CLIENT_TO_SERVER_LABEL, "CLIENT")
+    } else {
+        (CLIENT_TO_SERVER_LABEL, SERVER_TO_CLIENT_LABEL, "SERVER")
+    };
+    let outgoing = opts.direction(shared_secret, send_label);
+    let mut incoming = opts.direction(shared_secret, recv_label);
+    let mac_key = derive_key(shared_secret, MAC_KEY_LABEL);
+    print_cipher_info(opts.cipher, opts.lcg);
+    let first_nonce = outgoing.nonce(outgoing.next_seq);
+    print_keystream_preview(outgoing.keystream(&first_nonce).as_ref());
 
     println!("✓ Secure channel established!\n");
     println!("[CHAT] Type message:");
+    println!(
+        "(a line with just {} starts and ends a multi-line message)",
+        MULTILINE_DELIMITER
+    );
+
+    // Each direction has its own key, so the reader thread owns the
+    // receiving one and neither side ever waits on the other.
+    let reader_stream = stream.try_clone()?;
+    reader_stream.set_read_timeout(opts.timeout)?;
+    let reader_opts = opts.clone();
+    let reader = thread::spawn(move || {
+        // Until its hello arrives the peer is known by its role.
+        let mut peer_name = peer_label.to_string();
+        let result = receive_loop(
+            reader_stream,
+            &mut incoming,
+            &mac_key,
+            &reader_opts,
+            |event| match event {
+                ChatEvent::Hello(message) => {
+                    peer_name = sanitize_handle(message);
+                    println!("\n[{}] Handle: {}", peer_label, peer_name);
+                }
+                ChatEvent::Text(message) => {
+                    println!(
+                        "\n{}[{}] {}",
+                        reader_opts.stamp(),
+                        peer_name,
+                        indent_lines(message)
+                    );
+                    reader_opts.log("received", &peer_name, message);
+                }
+                ChatEvent::Leave => println!("\n[{}] left the chat", peer_name),
+            },
+        );
+        println!("\n[INFO] Connection closed");
+        // The main thread may still be blocked on stdin, so end the chat here.
+        match result {
+            Ok(()) => process::exit(0),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1)
+            }
+        }
+    });
+
+    // Stdin lines are queued for a writer thread, which also fills idle
+    // periods with heartbeats.
+    let (sender, writer) = spawn_writer(stream, outgoing, mac_key, opts.framing);
+    let writer_slot: WriterSlot = Arc::new(Mutex::new(Some((sender.clone(), writer))));
+
+    // Ctrl-C behaves like /quit: the peer hears that we left before we exit.
+    let interrupt_slot = Arc::clone(&writer_slot);
+    let interrupt_opts = opts.clone();
+    on_interrupt(move || {
+        if let Some((sender, writer)) = interrupt_slot.lock().unwrap().take() {
+            let _ = sender.send((MessageKind::Leave, Vec::new()));
+            let _ = writer.join();
+        }
+        interrupt_opts.flush_log();
+        println!("\n[INFO] Interrupted, left the chat");
+        process::exit(0);
+    })?;
+
+    let queue = |kind: MessageKind, body: &[u8]| {
+        sender
+            .send((kind, body.to_vec()))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))
+    };
+
+    // Handles travel over the encrypted channel like any other message.
+    debug_println!("[HELLO] Sending our handle: {}", opts.name);
+    queue(MessageKind::Hello, opts.name.as_bytes())?;
 
-    let stdin = io::stdin();
-    for line in stdin.lock().lines() {
-        let message = line?;
+    let mut lines = io::stdin().lock().lines();
+    while let Some(message) = next_message(&mut lines) {
+        let message = message?;
+        if message.trim() == QUIT_COMMAND {
+            opts.echo_sent(QUIT_COMMAND);
+            queue(MessageKind::Leave, &[])?;
+            break;
+        }
+        if let Some(path) = parse_send(&message) {
+            let path = Path::new(path);
+            match send_file(queue, path) {
+                Ok(()) => println!("[FILE] Queued {} for sending", path.display()),
+                Err(e) => println!("[WARN] Cannot send {}: {}", path.display(), e),
+            }
+            continue;
+        }
         if message.trim().is_empty() {
             continue;
         }
+        opts.echo_sent(&message);
+        opts.log("sent", &opts.name, &message);
+        queue(MessageKind::Text, message.as_bytes())?;
+    }
 
-        print!("> ");
-        io::stdout().flush()?;
-        println!("{}", message);
+    // Nothing more to send (the writer shuts down our half of the socket),
+    // but keep printing until the peer is done too.
+    let slot = writer_slot.lock().unwrap().take();
+    drop(sender);
+    if let Some((sender, writer)) = slot {
+        drop(sender);
+        let _ = writer.join();
+    }
+    let _ = reader.join();
+    Ok(())
+}
 
-        println!("\n[ENCRYPT]");
-        let plain_bytes = message.as_bytes();
-        print!("Plain: ");
-        for &b in plain_bytes.iter().take(plain_bytes.len().min(8)) {
-            print!("{:02x} ", b);
+/// Start the thread that encrypts and sends queued messages on `stream`,
+/// sending a heartbeat whenever the queue has been idle for
+/// `HEARTBEAT_INTERVAL`. It stops after a leave notice or once every sender
+/// is dropped, then shuts down the write half of the connection.
+fn spawn_writer(
+    mut stream: TcpStream,
+    mut outgoing: Direction,
+    mac_key: [u8; 32],
+    framing: Framing,
+) -> (mpsc::SyncSender<Outgoing>, thread::JoinHandle<()>) {
+    let (sender, queue) = mpsc::sync_channel::<Outgoing>(OUTGOING_QUEUE_LEN);
+    let writer = thread::spawn(move || {
+        let mut upload: Option<Progress> = None;
+        loop {
+            let (kind, body) = match queue.recv_timeout(HEARTBEAT_INTERVAL) {
+                Ok(item) => item,
+                Err(RecvTimeoutError::Timeout) => (MessageKind::Heartbeat, Vec::new()),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let sent = send_message(&mut stream, framing, kind, &body, &mut outgoing, &mac_key);
+            if let Err(e) = sent {
+                println!("\n[WARN] Send failed: {}", e);
+                break;
+            }
+            match kind {
+                MessageKind::FileStart => {
+                    let size = u64::from_be_bytes(body[..8].try_into().unwrap());
+                    let name = String::from_utf8_lossy(&body[8..]).into_owned();
+                    upload = Some(Progress::new(name, size));
+                }
+                MessageKind::FileChunk => {
+                    if let Some(progress) = upload.as_mut() {
+                        progress.advance(body.len());
+                    }
+                }
+                MessageKind::FileEnd => {
+                    if let Some(progress) = upload.take() {
+                        progress.finish();
+                        println!("[FILE] Sent {} ({} bytes)", progress.label, progress.done);
+                    }
+                }
+                _ => {}
+            }
+            if kind == MessageKind::Leave {
+                break;
+            }
         }
-        print!("({:?})", message);
-        println!();
+        let _ = stream.shutdown(Shutdown::Write);
+    });
+    (sender, writer)
+}
+
+/// Encrypt `plain` under a fresh nonce: the bytes that go on the wire are
+/// nonce || ciphertext || HMAC(nonce || ciphertext).
+fn seal_message(plain: &[u8], direction: &mut Direction, mac_key: &[u8; 32]) -> Vec<u8> {
+    let nonce = direction.next_nonce();
+    let mut payload = nonce.to_vec();
+    payload.extend(direction.cipher(&nonce).encrypt(plain));
+    direction.consume_keystream(plain.len());
+    let tag = compute_mac(mac_key, &payload);
+    payload.extend_from_slice(&tag);
+    payload
+}
+
+/// Verify and decrypt a payload built by `seal_message`, rebuilding the
+/// cipher from the nonce it carries. Duplicate or out-of-order sequence
+/// numbers are rejected.
+fn open_message(
+    payload: &[u8],
+    direction: &mut Direction,
+    mac_key: &[u8; 32],
+) -> Result<Vec<u8>, String> {
+    if payload.len() <= NONCE_LEN + MAC_LEN {
+        return Err(format!("malformed message ({} bytes)", payload.len()));
+    }
+    let (body, tag) = payload.split_at(payload.len() - MAC_LEN);
+    if !verify_mac(mac_key, body, tag) {
+        return Err(format!(
+            "HMAC verification failed ({} bytes)",
+            body.len() - NONCE_LEN
+        ));
+    }
+    let (nonce, encrypted) = body.split_at(NONCE_LEN);
+    direction.check_sequence(u64::from_be_bytes(nonce[4..].try_into().unwrap()))?;
+    direction.consume_keystream(encrypted.len());
+    Ok(direction
+        .cipher(nonce.try_into().unwrap())
+        .decrypt(encrypted))
+}
+
+/// Encrypt and send one message. The first plaintext byte carries its kind.
+fn send_message(
+    stream: &mut TcpStream,
+    framing: Framing,
+    kind: MessageKind,
+    body: &[u8],
+    direction: &mut Direction,
+    mac_key: &[u8; 32],
+) -> io::Result<()> {
+    let mut plain_bytes = vec![kind as u8];
+    plain_bytes.extend_from_slice(body);
+    if !matches!(
+        kind,
+        MessageKind::Text | MessageKind::Hello | MessageKind::Leave
+    ) {
+        // Keep the walkthrough readable: heartbeats and file transfers go out silently.
+        return framing.write(stream, &seal_message(&plain_bytes, direction, mac_key));
+    }
+
+    debug_println!("\n[ENCRYPT]");
+    debug_print!("Plain: ");
+    for &b in plain_bytes.iter().take(plain_bytes.len().min(8)) {
+        debug_print!("{:02x} ", b);
+    }
+    debug_print!("({:?})", String::from_utf8_lossy(body));
+    debug_println!();
+
+    let seq = direction.next_seq;
+    direction.advance_to(seq);
+    let nonce = direction.nonce(seq);
+    let key_bytes = direction
+        .keystream(&nonce)
+        .peek_bytes(plain_bytes.len().min(4));
+
+    debug_println!("Nonce: {}", hex::encode(nonce));
+    debug_print!("Key: ");
+    for &b in key_bytes.iter() {
+        debug_print!("{:02x} ", b);
+    }
+    debug_println!();
+
+    let payload = seal_message(&plain_bytes, direction, mac_key);
+    let (body, tag) = payload.split_at(payload.len() - MAC_LEN);
+    let encrypted = &body[NONCE_LEN..];
+    debug_print!("Cipher: ");
+    for &b in encrypted.iter().take(encrypted.len().min(5)) {
+        debug_print!("{:02x} ", b);
+    }
+    debug_println!();
+
+    debug_print!("MAC: ");
+    for &b in tag.iter().take(8) {
+        debug_print!("{:02x} ", b);
+    }
+    debug_println!("... (HMAC-SHA256)");
+
+    debug_println!(
+        "\n[NETWORK] Sending encrypted message ({} bytes)...",
+        encrypted.len()
+    );
+    framing.write(stream, &payload)?;
+    debug_println!("[-] Sent {} bytes", encrypted.len());
+
+    Ok(())
+}
+
+fn receive_loop(
+    stream: TcpStream,
+    direction: &mut Direction,
+    mac_key: &[u8; 32],
+    opts: &ChatOptions,
+    mut on_event: impl FnMut(ChatEvent),
+) -> io::Result<()> {
+    let timeout = stream.read_timeout()?;
+    let mut reader = BufReader::new(stream);
+    let mut download: Option<IncomingFile> = None;
+
+    loop {
+        let payload = match opts.framing.read(&mut reader) {
+            Ok(Some(payload)) => payload,
+            Ok(None) => break,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                println!(
+                    "\n[WARN] No message or heartbeat for {}s, peer looks dead",
+                    timeout.map_or(0, |t| t.as_secs())
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let decrypted = match open_message(&payload, direction, mac_key) {
+            Ok(decrypted) => decrypted,
+            Err(e) => {
+                println!("\n[WARN] Dropping message: {}", e);
+                continue;
+            }
+        };
 
-        let position = (keystream.state as usize) % (LCG_M as usize);
-        let key_bytes: Vec<u8> = (0..plain_bytes.len().min(4))
-            .map(|i| {
-                let mut temp = keystream.state;
-                for _ in 0..i {
-                    temp = ((temp as u128 * LCG_A as u128 + LCG_C as u128) % LCG_M as u128) as u64;
+        let kind = match decrypted.first().copied().and_then(MessageKind::from_byte) {
+            Some(kind) => kind,
+            None => {
+                println!("\n[WARN] Dropping message: unknown message kind");
+                continue;
+            }
+        };
+        let body = &decrypted[1..];
+        match kind {
+            MessageKind::Heartbeat => continue,
+            MessageKind::FileStart => {
+                if let Some(partial) = download.take() {
+                    println!("\n[WARN] Incomplete file kept: {}", partial.path.display());
                 }
-                (temp & 0xFF) as u8
-            })
-            .collect();
+                match IncomingFile::start(body, &opts.download_dir) {
+                    Ok(file) => {
+                        println!(
+                            "\n[FILE] Receiving {} ({} bytes)",
+                            file.path.display(),
+                            file.expected
+                        );
+                        download = Some(file);
+                    }
+                    Err(e) => println!("\n[WARN] Refusing file: {}", e),
+                }
+                continue;
+            }
+            MessageKind::FileChunk => {
+                if let Some(file) = download.as_mut() {
+                    if let Err(e) = file.write_chunk(body) {
+                        println!("\n[WARN] Cannot write {}: {}", file.path.display(), e);
+                        download = None;
+                    }
+                }
+                continue;
+            }
+            MessageKind::FileEnd => {
+                if let Some(file) = download.take() {
+                    file.progress.finish();
+                    if file.progress.done == file.expected {
+                        println!(
+                            "[FILE] Saved {} ({} bytes)",
+                            file.path.display(),
+                            file.expected
+                        );
+                    } else {
+                        println!(
+                            "[WARN] {} is incomplete: got {} of {} bytes",
+                            file.path.display(),
+                            file.progress.done,
+                            file.expected
+                        );
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let (nonce, encrypted) = payload[..payload.len() - MAC_LEN].split_at(NONCE_LEN);
+        let key_bytes = direction
+            .keystream(nonce.try_into().unwrap())
+            .peek_bytes(encrypted.len().min(3));
+
+        debug_println!(
+            "\n[NETWORK] Received encrypted message ({} bytes)",
+            encrypted.len()
+        );
+        debug_println!("[-] Received {} bytes", encrypted.len());
+        debug_println!("[MAC] HMAC-SHA256 verified ✓");
+
+        debug_println!("\n[DECRYPT]");
+        debug_print!("Cipher: ");
+        for &b in encrypted.iter().take(3) {
+            debug_print!("{:02x} ", b);
+        }
+        debug_println!();
 
-        print!("Key: ");
+        debug_println!("Nonce: {}", hex::encode(nonce));
+        debug_print!("Key: ");
         for &b in key_bytes.iter() {
-            print!("{:02x} ", b);
+            debug_print!("{:02x} ", b);
         }
-        println!(" (keystream position: {})", position);
+        debug_println!();
 
-        let encrypted = xor_cipher(plain_bytes, &mut keystream);
-        print!("Cipher: ");
-        for &b in encrypted.iter().take(encrypted.len().min(5)) {
-            print!("{:02x} ", b);
+        let message = String::from_utf8_lossy(body);
+        debug_print!("Plain: ");
+        for &b in decrypted.iter().take(3) {
+            debug_print!("{:02x} ", b);
         }
-        println!();
+        debug_println!("→ {:?}", message);
 
-        let hex_message = hex::encode(&encrypted);
-        println!(
-            "\n[NETWORK] Sending encrypted message ({} bytes)...",
-            encrypted.len()
+        debug_println!(
+            "\n[TEST] Round-trip verified: {:?} → encrypt → decrypt → {:?} ✓",
+            message,
+            message
         );
-        stream.write_all(hex_message.as_bytes())?;
-        stream.write_all(b"\n")?;
-        stream.flush()?;
-        println!("[-] Sent {} bytes", encrypted.len());
+        match kind {
+            MessageKind::Hello => on_event(ChatEvent::Hello(&message)),
+            MessageKind::Text => on_event(ChatEvent::Text(&message)),
+            MessageKind::Leave => {
+                on_event(ChatEvent::Leave);
+                break;
+            }
+            _ => unreachable!("handled above"),
+        }
     }
 
+    if let Some(partial) = download {
+        println!("\n[WARN] Incomplete file kept: {}", partial.path.display());
+    }
     Ok(())
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
+fn main() {
+    if let Err(e) = run(Args::parse()) {
+        eprintln!("[ERROR] {}", e);
+        process::exit(1);
+    }
+}
+
+/// Time `iterations` DH-sized exponentiations on random inputs. Each input
+/// is drawn just before its exponentiation, and only the arithmetic is timed.
+fn run_bench(iterations: Option<u64>, strong_dh: bool) {
+    let iterations = iterations.unwrap_or(if strong_dh {
+        STRONG_BENCH_ITERATIONS
+    } else {
+        BENCH_ITERATIONS
+    });
+    let mut elapsed = Duration::ZERO;
+    let label;
+    if strong_dh {
+        let prime = modp_2048_prime();
+        let g = BigUint::from(G);
+        label = "mod_exp_big (2048-bit modulus)";
+        for _ in 0..iterations {
+            let mut bytes = [0u8; STRONG_PRIVATE_BYTES];
+            OsRng.fill_bytes(&mut bytes);
+            let exp = BigUint::from_bytes_be(&bytes);
+            let start = Instant::now();
+            hint::black_box(mod_exp_big(&g, &exp, &prime));
+            elapsed += start.elapsed();
+        }
+    } else {
+        label = "mod_exp (64-bit modulus)";
+        for _ in 0..iterations {
+            let (base, exp) = (generate_random() % P, generate_random());
+            let start = Instant::now();
+            hint::black_box(mod_exp(base, exp, P));
+            elapsed += start.elapsed();
+        }
+    }
+
+    println!("[BENCH] {}", label);
+    println!("Iterations: {}", iterations);
+    println!("Total: {:.3} s", elapsed.as_secs_f64());
+    println!(
+        "Per op: {:.3} µs",
+        elapsed.as_secs_f64() * 1e6 / iterations as f64
+    );
+    println!(
+        "Throughput: {:.0} ops/s",
+        iterations as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn run(args: Args) -> io::Result<()> {
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    if let Command::Bench { iterations } = args.command {
+        run_bench(iterations, args.strong_dh);
+        return Ok(());
+    }
+    if args.lcg_a >= args.lcg_m {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ValueValidation,
+                format!(
+                    "--lcg-a ({}) must be less than --lcg-m ({})",
+                    args.lcg_a, args.lcg_m
+                ),
+            )
+            .exit();
+    }
+    let opts = ChatOptions {
+        cipher: args.cipher,
+        lcg: LcgParams {
+            a: args.lcg_a,
+            c: args.lcg_c,
+            m: args.lcg_m,
+        },
+        strong_dh: args.strong_dh,
+        name: args.name,
+        timestamps: args.timestamps,
+        timeout: args.timeout.map(Duration::from_secs),
+        framing: if args.binary_frames {
+            Framing::Binary
+        } else {
+            Framing::Lines(args.encoding)
+        },
+        rekey_every: args.rekey_every,
+        download_dir: args.download_dir,
+        log: args.log.as_deref().map(Transcript::open).transpose()?,
+    };
 
     match args.command {
-        Command::Server { port } => run_server(port),
-        Command::Client { address } => run_client(address),
+        Command::Server {
+            port,
+            bind,
+            broadcast,
+        } => run_server(bind, port, broadcast, opts),
+        Command::Client { address } => run_client(address, opts),
+        Command::Bench { .. } => unreachable!("handled above"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_directions_decrypt_when_interleaved() {
+        let secret = &0x1234_5678_9ABC_DEF0u64.to_be_bytes();
+        let mac_key = derive_key(secret, MAC_KEY_LABEL);
+
+        for cipher in [
+            CipherKind::Chacha20,
+            CipherKind::AesCtr,
+            CipherKind::LcgInsecure,
+        ] {
+            let mut client_send = Direction::new(cipher, secret, CLIENT_TO_SERVER_LABEL);
+            let mut client_recv = Direction::new(cipher, secret, SERVER_TO_CLIENT_LABEL);
+            let mut server_send = Direction::new(cipher, secret, SERVER_TO_CLIENT_LABEL);
+            let mut server_recv = Direction::new(cipher, secret, CLIENT_TO_SERVER_LABEL);
+
+            // Both peers send before either reads, as happens when typing at once.
+            let to_server = seal_message(b"hello server", &mut client_send, &mac_key);
+            let to_client = seal_message(b"hello client", &mut server_send, &mac_key);
+            let to_server_2 = seal_message(b"second", &mut client_send, &mac_key);
+
+            assert_eq!(
+                open_message(&to_client, &mut client_recv, &mac_key).unwrap(),
+                b"hello client"
+            );
+            assert_eq!(
+                open_message(&to_server, &mut server_recv, &mac_key).unwrap(),
+                b"hello server"
+            );
+            assert_eq!(
+                open_message(&to_server_2, &mut server_recv, &mac_key).unwrap(),
+                b"second"
+            );
+        }
+    }
+
+    #[test]
+    fn identical_plaintexts_get_distinct_ciphertexts() {
+        let secret = &0x0BAD_F00D_DEAD_BEEFu64.to_be_bytes();
+        let mac_key = derive_key(secret, MAC_KEY_LABEL);
+
+        for cipher in [
+            CipherKind::Chacha20,
+            CipherKind::AesCtr,
+            CipherKind::LcgInsecure,
+        ] {
+            let mut sender = Direction::new(cipher, secret, CLIENT_TO_SERVER_LABEL);
+            let first = seal_message(b"same text", &mut sender, &mac_key);
+            let second = seal_message(b"same text", &mut sender, &mac_key);
+            assert_ne!(first[..NONCE_LEN], second[..NONCE_LEN]);
+            assert_ne!(first[NONCE_LEN..], second[NONCE_LEN..]);
+
+            let mut receiver = Direction::new(cipher, secret, CLIENT_TO_SERVER_LABEL);
+            assert_eq!(
+                open_message(&first, &mut receiver, &mac_key).unwrap(),
+                b"same text"
+            );
+            assert_eq!(
+                open_message(&second, &mut receiver, &mac_key).unwrap(),
+                b"same text"
+            );
+        }
+    }
+
+    #[test]
+    fn rekeying_stays_in_sync_across_dropped_messages() {
+        let secret = &0x5EED_5EED_5EED_5EEDu64.to_be_bytes();
+        let mac_key = derive_key(secret, MAC_KEY_LABEL);
+        let mut sender = Direction::new(CipherKind::Chacha20, secret, CLIENT_TO_SERVER_LABEL)
+            .with_rekey_every(2);
+        let mut receiver = Direction::new(CipherKind::Chacha20, secret, CLIENT_TO_SERVER_LABEL)
+            .with_rekey_every(2);
+        let initial_key = sender.key;
+
+        let sealed: Vec<_> = (0..7)
+            .map(|i| seal_message(format!("msg {}", i).as_bytes(), &mut sender, &mac_key))
+            .collect();
+        assert_eq!(sender.epoch, 3);
+        assert_ne!(sender.key, initial_key);
+
+        // Messages 2 and 3 (all of epoch 1) never arrive.
+        for i in [0, 1, 4, 5, 6] {
+            assert_eq!(
+                open_message(&sealed[i], &mut receiver, &mac_key).unwrap(),
+                format!("msg {}", i).as_bytes()
+            );
+        }
+        assert_eq!(receiver.key, sender.key);
+    }
+
+    #[test]
+    fn lcg_keystream_usage_warns_once_per_key() {
+        let secret = &0xABCD_EF01_2345_6789u64.to_be_bytes();
+        let mac_key = derive_key(secret, MAC_KEY_LABEL);
+        let mut sender = Direction::new(CipherKind::LcgInsecure, secret, CLIENT_TO_SERVER_LABEL)
+            .with_rekey_every(2);
+        assert_eq!(sender.keystream_limit, Some(LCG_M / 2));
+        sender.keystream_limit = Some(20);
+
+        seal_message(&[0; 12], &mut sender, &mac_key);
+        assert_eq!(sender.keystream_used, 12);
+        assert!(!sender.keystream_warned);
+        seal_message(&[0; 12], &mut sender, &mac_key);
+        assert!(sender.keystream_warned);
+        assert!(!sender.consume_keystream(100), "warns only once");
+
+        // A rekey starts a fresh keystream budget.
+        seal_message(&[0; 4], &mut sender, &mac_key);
+        assert_eq!(sender.keystream_used, 4);
+        assert!(!sender.keystream_warned);
+
+        let chacha = Direction::new(CipherKind::Chacha20, secret, CLIENT_TO_SERVER_LABEL);
+        assert_eq!(chacha.keystream_limit, None);
+    }
+
+    #[test]
+    fn replayed_and_stale_messages_are_rejected() {
+        let secret = &0xFEED_FACE_CAFE_BABEu64.to_be_bytes();
+        let mac_key = derive_key(secret, MAC_KEY_LABEL);
+        let mut sender = Direction::new(CipherKind::Chacha20, secret, CLIENT_TO_SERVER_LABEL);
+        let mut receiver = Direction::new(CipherKind::Chacha20, secret, CLIENT_TO_SERVER_LABEL);
+
+        let first = seal_message(b"one", &mut sender, &mac_key);
+        let second = seal_message(b"two", &mut sender, &mac_key);
+
+        assert_eq!(
+            open_message(&second, &mut receiver, &mac_key).unwrap(),
+            b"two"
+        );
+        assert!(open_message(&second, &mut receiver, &mac_key).is_err());
+        assert!(open_message(&first, &mut receiver, &mac_key).is_err());
+
+        let third = seal_message(b"three", &mut sender, &mac_key);
+        assert_eq!(
+            open_message(&third, &mut receiver, &mac_key).unwrap(),
+            b"three"
+        );
+    }
+
+    #[test]
+    fn line_encodings_round_trip() {
+        let payload = [0x00, 0xFF, 0x10, 0x80, 0x7F];
+        assert_eq!(Encoding::Hex.encode(&payload), "00ff10807f");
+        assert_eq!(Encoding::Base64.encode(&payload), "AP8QgH8=");
+        for encoding in [Encoding::Hex, Encoding::Base64] {
+            assert_eq!(encoding.decode(&encoding.encode(&payload)), payload);
+        }
+        assert!(Encoding::Base64.decode("not base64!").is_empty());
+    }
+
+    #[test]
+    fn multi_line_messages_are_joined_between_delimiters() {
+        let input = "one\n\"\"\"\nfirst\n  second\n\"\"\"\ntwo\n\"\"\"\nunfinished\n";
+        let mut lines = input.lines().map(|line| Ok(line.to_string()));
+        let messages: Vec<String> = std::iter::from_fn(|| next_message(&mut lines))
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(messages, ["one", "first\n  second", "two", "unfinished"]);
+        assert_eq!(indent_lines("a\nb"), "a\n    b");
+    }
+
+    #[test]
+    fn whisper_commands_are_parsed() {
+        assert_eq!(
+            parse_whisper("/whisper bob hi there"),
+            Some(Ok(("bob", "hi there")))
+        );
+        assert_eq!(
+            parse_whisper("  /whisper  bob   hi "),
+            Some(Ok(("bob", "hi")))
+        );
+        assert!(matches!(parse_whisper("/whisper bob"), Some(Err(_))));
+        assert!(matches!(parse_whisper("/whisper"), Some(Err(_))));
+        assert_eq!(parse_whisper("/whispering is rude"), None);
+        assert_eq!(parse_whisper("hello"), None);
+    }
+
+    #[test]
+    fn duplicate_handles_get_a_number() {
+        let clients: ClientList = Arc::default();
+        for id in 0..3 {
+            let (sender, _queue) = mpsc::sync_channel(1);
+            let writer = thread::spawn(|| {});
+            let handle = format!("#{}", id);
+            let client = ClientHandle {
+                handle,
+                sender,
+                writer,
+            };
+            clients.lock().unwrap().insert(id, client);
+        }
+
+        assert_eq!(claim_handle(&clients, 0, "bob".into()), "bob");
+        assert_eq!(claim_handle(&clients, 1, "bob".into()), "bob#2");
+        assert_eq!(claim_handle(&clients, 2, "bob".into()), "bob#3");
+        // Re-sending the same hello keeps the handle a client already has.
+        assert_eq!(claim_handle(&clients, 0, "bob".into()), "bob");
+    }
+
+    #[test]
+    fn send_commands_need_a_separate_path() {
+        assert_eq!(parse_send("/send notes.txt"), Some("notes.txt"));
+        assert_eq!(parse_send("  /send  notes.txt "), Some("notes.txt"));
+        assert_eq!(parse_send("/send"), Some(""));
+        assert_eq!(parse_send("/sendfoo"), None);
+        assert_eq!(parse_send("/sendmail is down"), None);
+    }
+
+    #[test]
+    fn settings_round_trip_and_report_mismatches() {
+        let ours = Settings {
+            version: PROTOCOL_VERSION,
+            cipher: CipherKind::LcgInsecure,
+            framing: Framing::Lines(Encoding::Base64),
+            rekey_every: 42,
+            lcg: LcgParams { a: 5, c: 1, m: 256 },
+        };
+        assert_eq!(Settings::from_bytes(&ours.to_bytes()), Ok(ours));
+        assert!(ours.mismatches(&ours).is_empty());
+
+        let peer = Settings {
+            cipher: CipherKind::Chacha20,
+            framing: Framing::Binary,
+            ..ours
+        };
+        let problems = ours.mismatches(&peer);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("lcg-insecure") && problems[0].contains("chacha20"));
+        assert!(problems[1].contains("--binary-frames"));
+
+        let mut future = ours.to_bytes();
+        future[0] = PROTOCOL_VERSION + 1;
+        assert!(Settings::from_bytes(&future).is_err());
+    }
+
+    #[test]
+    fn utc_datetime_formats_known_instants() {
+        assert_eq!(utc_datetime(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(utc_datetime(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(utc_datetime(1_700_000_000), "2023-11-14 22:13:20 UTC");
+    }
+
+    #[test]
+    fn resolve_address_accepts_v4_and_bracketed_v6() {
+        assert_eq!(
+            resolve_address("127.0.0.1:4000").unwrap(),
+            vec!["127.0.0.1:4000".parse::<SocketAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_address("[::1]:4000").unwrap(),
+            vec!["[::1]:4000".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(resolve_address("::1:4000").is_err());
+        assert!(resolve_address("::1").is_err());
+        assert!(resolve_address("127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn received_file_names_stay_in_the_download_dir() {
+        assert_eq!(safe_file_name("notes.txt").as_deref(), Some("notes.txt"));
+        assert_eq!(
+            safe_file_name("../../etc/passwd").as_deref(),
+            Some("passwd")
+        );
+        assert_eq!(safe_file_name("/tmp/x.bin").as_deref(), Some("x.bin"));
+        assert_eq!(safe_file_name(".."), None);
+        assert_eq!(safe_file_name(""), None);
+    }
+
+    #[test]
+    fn received_files_stop_at_the_announced_size() {
+        let dir = std::env::temp_dir().join(format!("streamchat_{}_oversize", process::id()));
+        let mut header = 4u64.to_be_bytes().to_vec();
+        header.extend_from_slice(b"small.bin");
+        let mut file = IncomingFile::start(&header, &dir).unwrap();
+
+        file.write_chunk(b"abc").unwrap();
+        assert!(file.write_chunk(b"de").is_err());
+        file.write_chunk(b"d").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -1,16 +1,16 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Read, Write};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Find min/max cost paths in hexadecimal grid
 #[derive(Parser, Debug)]
 #[command(name = "hexpath", about, long_about = None, disable_version_flag = true)]
 struct Args {
-    /// Map file (hex values, space separated)
+    /// Map file (hex values, space or comma separated), or - to read stdin
     map_file: Option<String>,
 
     /// Generate random map (e.g., 8x4, 10x10)
@@ -21,84 +21,495 @@ struct Args {
     #[arg(long)]
     output: Option<String>,
 
+    /// Cell separator of the --output map file (maps are read in either
+    /// format)
+    #[arg(long, value_enum, default_value_t = MapFormat::Space, requires = "output")]
+    format: MapFormat,
+
+    /// Seed for --generate; the same seed gives the same map [default: time-based]
+    #[arg(long, requires = "generate")]
+    seed: Option<u64>,
+
+    /// Share of generated cells that are obstacles, 0.0-1.0: half walls (XX),
+    /// half high-cost cells (C0-FF); the other cells are open terrain (00-BF)
+    /// [default: uniform 00-FF, no walls]
+    #[arg(long, requires = "generate", value_parser = parse_density)]
+    density: Option<f64>,
+
+    /// Regenerate (with the next seed) until the end is reachable from the start
+    #[arg(long, requires = "generate")]
+    solvable: bool,
+
     /// Show colored map
     #[arg(long)]
     visualize: bool,
 
-    /// Show both min and max paths
+    /// Number the columns (X) and rows (Y) of the --visualize grids; column
+    /// numbers above 99 wrap to keep two characters per cell
+    #[arg(long, requires = "visualize")]
+    labels: bool,
+
+    /// Draw the --visualize minimum cost path as arrows in the direction of
+    /// travel instead of cell values
+    #[arg(long, requires = "visualize")]
+    arrows: bool,
+
+    /// Print cell count, average/min/max cell value and direction changes
+    /// for each path
+    #[arg(long)]
+    stats: bool,
+
+    /// Show both min and max paths (the max path only moves right/down)
     #[arg(long)]
     both: bool,
 
     /// Animate pathfinding
     #[arg(long)]
     animate: bool,
+
+    /// Pause between animation frames, in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 100, requires = "animate")]
+    animate_delay: u64,
+
+    /// Draw a frame every N expanded cells (plus the first two)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 10,
+        requires = "animate",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    animate_every: u64,
+
+    /// Search algorithm for the minimum cost path
+    #[arg(long, value_enum, default_value_t = Algorithm::Dijkstra)]
+    algorithm: Algorithm,
+
+    /// Multiply the A* estimate by W: above 1 expands fewer cells but the
+    /// path may no longer be the cheapest
+    #[arg(long, value_name = "W", default_value_t = 1.0, value_parser = parse_weight)]
+    heuristic_weight: f64,
+
+    /// How cell values combine into a path cost
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = CostModel::Add,
+        conflicts_with_all = ["bottleneck", "both", "count_paths", "reachable"]
+    )]
+    cost_model: CostModel,
+
+    /// Extra cost each time the path changes direction (diagonals count as
+    /// their own directions)
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with_all = ["bottleneck", "cost_model", "count_paths", "reachable"]
+    )]
+    turn_penalty: u32,
+
+    /// Minimize the highest cell cost on the path instead of the sum
+    #[arg(long, conflicts_with_all = ["both", "count_paths"])]
+    bottleneck: bool,
+
+    /// Leave the start cell out of path costs, so only the cells moved into
+    /// count [default: every cell of the path counts, the start included]
+    #[arg(long)]
+    exclude_start: bool,
+
+    /// Show the cost to reach each cell from the minimum cost search as a heat map
+    #[arg(long, conflicts_with = "json")]
+    heatmap: bool,
+
+    /// Instead of a path, mark every cell reachable from the start with a path
+    /// costing at most BUDGET (shown as a map with --visualize)
+    #[arg(
+        long,
+        value_name = "BUDGET",
+        conflicts_with_all = ["both", "count_paths", "animate", "json"]
+    )]
+    reachable: Option<u64>,
+
+    /// List the K cheapest distinct paths from start to end instead of one
+    #[arg(
+        long,
+        value_name = "K",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = [
+            "both", "count_paths", "animate", "json", "visualize", "reachable",
+            "bottleneck", "cost_model", "turn_penalty"
+        ]
+    )]
+    k_paths: Option<u32>,
+
+    /// Instead of a path, check which open cells can be reached from the
+    /// start at all, whatever the cost
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "both", "count_paths", "animate", "json", "visualize", "reachable", "k_paths"
+        ]
+    )]
+    connectivity: bool,
+
+    /// Also move diagonally (8 neighbors instead of 4)
+    #[arg(long)]
+    diagonals: bool,
+
+    /// Treat the grid as hexagonal cells (6 neighbors): odd rows sit half a
+    /// cell to the right of even rows
+    #[arg(long, conflicts_with = "diagonals")]
+    hex_grid: bool,
+
+    /// Start cell as X,Y (column, row) [default: 0,0]
+    #[arg(long, value_name = "X,Y", value_parser = parse_coord)]
+    start: Option<(usize, usize)>,
+
+    /// End cell as X,Y (column, row) [default: bottom-right corner]
+    #[arg(long, value_name = "X,Y", value_parser = parse_coord)]
+    end: Option<(usize, usize)>,
+
+    /// Also treat cells with this hex value as walls (XX cells always are)
+    #[arg(long, value_name = "HEX", value_parser = parse_hex_value)]
+    wall_value: Option<u32>,
+
+    /// Allow map cells above FF (up to FFFFFFFF); colors then spread from the
+    /// lowest to the highest value in the map
+    #[arg(long)]
+    wide: bool,
+
+    /// Also report how many distinct paths reach the minimum cost (exact
+    /// when every cell costs at least 01; 00 cells make it a lower bound)
+    #[arg(long)]
+    count_paths: bool,
+
+    /// Print the result as JSON instead of text
+    #[arg(long, conflicts_with_all = ["visualize", "animate"])]
+    json: bool,
+
+    /// Also render the grid and paths to an SVG file
+    #[arg(long, value_name = "PATH")]
+    export_svg: Option<String>,
+
+    /// Print search times and expanded cell counts to stderr
+    #[arg(long)]
+    timing: bool,
+
+    /// Give up the minimum cost search after MS milliseconds
+    #[arg(long, value_name = "MS", conflicts_with_all = ["reachable", "k_paths", "connectivity"])]
+    timeout: Option<u64>,
+
+    /// Plain text output without ANSI colors (the default when stdout is not a terminal)
+    #[arg(long)]
+    no_color: bool,
+}
+
+/// One map cell: its cost, or `None` for an impassable wall (`XX` in map files).
+type Cell = Option<u32>;
+
+/// A cell position as (row, column).
+type Pos = (usize, usize);
+
+fn parse_weight(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(w) if w.is_finite() && w >= 0.0 => Ok(w),
+        _ => Err(format!(
+            "Invalid weight '{}': expected a non-negative number",
+            s
+        )),
+    }
+}
+
+fn parse_density(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(d) if (0.0..=1.0).contains(&d) => Ok(d),
+        _ => Err(format!(
+            "Invalid density '{}': expected a number between 0.0 and 1.0",
+            s
+        )),
+    }
+}
+
+fn parse_hex_value(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("Invalid hex value '{}': expected 00 to FFFFFFFF", s))
+}
+
+/// Parse "X,Y" into a (row, column) position, the order used for indexing.
+fn parse_coord(s: &str) -> Result<(usize, usize), String> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("Invalid coordinate '{}': expected X,Y", s))?;
+    let x = x
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid column: '{}'", x.trim()))?;
+    let y = y
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid row: '{}'", y.trim()))?;
+    Ok((y, x))
+}
+
+/// Check that `pos` lies inside the grid; `what` names it in the error.
+fn check_inside(grid: &[Vec<Cell>], pos: (usize, usize), what: &str) -> Result<(), String> {
+    let (height, width) = (grid.len(), grid[0].len());
+    if pos.0 >= height || pos.1 >= width {
+        return Err(format!(
+            "{} ({},{}) is outside the {}x{} grid (X must be below {}, Y below {})",
+            what, pos.1, pos.0, width, height, width, height
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `pos` lies inside the grid and is not a wall; `what` names it
+/// in the error.
+fn validate_coord(grid: &[Vec<Cell>], pos: (usize, usize), what: &str) -> Result<(), String> {
+    check_inside(grid, pos, what)?;
+    if grid[pos.0][pos.1].is_none() {
+        return Err(format!("{} ({},{}) is a wall", what, pos.1, pos.0));
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Algorithm {
+    /// Expand cells in order of cost from the start
+    Dijkstra,
+    /// Expand cells in order of cost plus a distance-to-end estimate
+    Astar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum MapFormat {
+    /// Cells separated by spaces
+    Space,
+    /// Cells separated by commas, for spreadsheets
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum CostModel {
+    /// A path costs the sum of its cells
+    Add,
+    /// A path costs the product of its cells; 00 cells count as 01
+    Multiply,
+}
+
+/// Fixed-point scale of the logarithms the multiply cost model adds up.
+const LOG_SCALE: f64 = 10_000.0;
+
+/// Settings for the path searches.
+#[derive(Clone, Copy, Debug)]
+struct SearchOptions {
+    algorithm: Algorithm,
+    /// Factor applied to the A* estimate (weighted A*)
+    heuristic_weight: f64,
+    animate: bool,
+    /// Pause after each animation frame
+    animate_delay: Duration,
+    /// Expanded cells between animation frames
+    animate_every: usize,
+    /// ANSI colors in animation frames
+    color: bool,
+    diagonals: bool,
+    /// Six neighbors in an offset hexagonal layout (see `neighbors`)
+    hex_grid: bool,
+    /// (row, column) of the first cell of every path
+    start: (usize, usize),
+    /// (row, column) of the last cell of every path
+    end: (usize, usize),
+    /// Keep searching past the first arrival at the end so every minimum
+    /// cost path is counted
+    count_paths: bool,
+    /// A path costs its most expensive cell rather than the sum of its cells
+    bottleneck: bool,
+    /// The start cell adds nothing to path costs
+    exclude_start: bool,
+    cost_model: CostModel,
+    /// Added to the cost whenever the path changes direction
+    turn_penalty: u32,
+    /// How long the minimum cost search may run
+    timeout: Option<Duration>,
+}
+
+impl SearchOptions {
+    /// Whether the minimum cost search is guaranteed to return a cheapest
+    /// path: always for Dijkstra, and for A* while the weighted estimate
+    /// still never exceeds the true cost.
+    fn optimal(&self) -> bool {
+        self.algorithm == Algorithm::Dijkstra || self.heuristic_weight <= 1.0
+    }
+
+    /// Cost of a path costing `cost` extended by a step entering a cell
+    /// worth `step`; `extend(0, val)` is the cost of a cell entered from
+    /// nowhere (see `start_cost`).
+    ///
+    /// With the multiply model the search adds `ln(val)` in fixed point:
+    /// minimizing the sum of logarithms minimizes the product without
+    /// overflowing. A 00 cell would zero every product through it (the
+    /// generated start cell among them), so it counts as 01, i.e. free.
+    fn extend(&self, cost: u64, step: u32) -> u64 {
+        if self.bottleneck {
+            return cost.max(step.into());
+        }
+        match self.cost_model {
+            CostModel::Add => cost + u64::from(step),
+            CostModel::Multiply => cost + ((step.max(1) as f64).ln() * LOG_SCALE).round() as u64,
+        }
+    }
+
+    /// Cost of the path made of the start cell alone, worth `val`: the cell
+    /// itself, or nothing with `exclude_start`.
+    fn start_cost(&self, val: u32) -> u64 {
+        if self.exclude_start {
+            0
+        } else {
+            self.extend(0, val)
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 struct State {
-    cost: u32,
+    cost: u64,
+    /// Cost plus the A* estimate to the end; equal to `cost` for Dijkstra
+    priority: u64,
     pos: (usize, usize),
+    /// Direction of the step that entered `pos` (see `direction`)
+    dir: usize,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost)
+/// `State::dir` of the start cell, which no step entered.
+const NO_DIRECTION: usize = 4;
+
+/// Index 0-8 of the step from `from` to `to` in the 3x3 block of offsets
+/// around `from`; the center, `NO_DIRECTION`, is never a real step. On a hex
+/// grid the offsets are taken in axial coordinates, where a straight line
+/// keeps the same step whatever the row parity.
+fn direction(from: (usize, usize), to: (usize, usize), hex_grid: bool) -> usize {
+    if hex_grid {
+        let ((fq, fr), (tq, tr)) = (axial(from), axial(to));
+        return ((tr - fr + 1) * 3 + (tq - fq + 1)) as usize;
     }
+    (to.0 + 1 - from.0) * 3 + (to.1 + 1 - from.1)
 }
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+/// Axial coordinates (q, r) of a cell of the offset hex layout, in which
+/// odd rows are shifted half a cell right.
+fn axial(pos: (usize, usize)) -> (isize, isize) {
+    let (y, x) = (pos.0 as isize, pos.1 as isize);
+    (x - y / 2, y)
+}
+
+/// Arrow pointing from `from` to the neighboring cell `to` as the grid is
+/// drawn; on a hex grid a step to the next or previous row leans towards the
+/// side the shifted odd rows put it on.
+fn arrow(from: (usize, usize), to: (usize, usize), hex_grid: bool) -> char {
+    let dy = to.0 as isize - from.0 as isize;
+    let mut dx = to.1 as isize - from.1 as isize;
+    if hex_grid && dy != 0 && dx == 0 {
+        dx = if from.0 % 2 == 1 { -1 } else { 1 };
+    }
+    match (dy, dx) {
+        (-1, -1) => '↖',
+        (-1, 0) => '↑',
+        (-1, 1) => '↗',
+        (0, -1) => '←',
+        (0, 1) => '→',
+        (1, -1) => '↙',
+        (1, 0) => '↓',
+        (1, 1) => '↘',
+        _ => '·',
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct StateMax {
-    cost: u32,
-    pos: (usize, usize),
+/// Leading space shifting odd rows half a cell right in hex grid views.
+fn row_indent(y: usize, hex_grid: bool) -> &'static str {
+    if hex_grid && y % 2 == 1 {
+        " "
+    } else {
+        ""
+    }
 }
 
-impl Ord for StateMax {
+impl Ord for State {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.cost.cmp(&other.cost)
+        // On equal priority prefer the cell that has paid more, i.e. the one
+        // closer to the end, so A* does not fan out across equal-cost ties.
+        other
+            .priority
+            .cmp(&self.priority)
+            .then(self.cost.cmp(&other.cost))
     }
 }
 
-impl PartialOrd for StateMax {
+impl PartialOrd for State {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
 
-fn parse_map(content: &str) -> Result<Vec<Vec<u8>>, String> {
+/// Parse a map file: one row per line, cells as hex values or `XX` for a
+/// wall, separated by spaces or, on a line with a comma, by commas (CSV).
+/// Blank lines are ignored. Errors name the line (counting from 1) and,
+/// for a bad cell, its column.
+fn parse_map(content: &str) -> Result<Vec<Vec<Cell>>, String> {
     let mut grid = Vec::new();
-    for line in content.lines() {
+    let mut width = None;
+    for (number, line) in content.lines().enumerate().map(|(i, line)| (i + 1, line)) {
         let line = line.trim();
         if line.is_empty() {
             continue;
         }
-        let row: Result<Vec<u8>, _> = line
-            .split_whitespace()
-            .map(|s| u8::from_str_radix(s, 16))
-            .collect();
-        grid.push(row.map_err(|e| format!("Invalid hex value: {}", e))?);
+        let cells: Vec<&str> = if line.contains(',') {
+            line.split(',').map(str::trim).collect()
+        } else {
+            line.split_whitespace().collect()
+        };
+        let mut row = Vec::new();
+        for (column, s) in cells.into_iter().enumerate() {
+            if s.eq_ignore_ascii_case("XX") {
+                row.push(None);
+                continue;
+            }
+            let val = u32::from_str_radix(s, 16).map_err(|e| {
+                format!(
+                    "Line {}, column {}: invalid cell '{}' ({}); expected a hex value or XX",
+                    number,
+                    column + 1,
+                    s,
+                    e
+                )
+            })?;
+            row.push(Some(val));
+        }
+        let expected = *width.get_or_insert(row.len());
+        if row.len() != expected {
+            return Err(format!(
+                "Line {}: {} cells, but the first row has {}",
+                number,
+                row.len(),
+                expected
+            ));
+        }
+        grid.push(row);
     }
 
     if grid.is_empty() {
         return Err("Empty map".to_string());
     }
 
-    let width = grid[0].len();
-    for row in &grid {
-        if row.len() != width {
-            return Err("Inconsistent row lengths".to_string());
-        }
-    }
-
     Ok(grid)
 }
 
-fn generate_map(size_str: String) -> Result<Vec<Vec<u8>>, String> {
+fn generate_map(
+    size_str: String,
+    seed: u64,
+    density: Option<f64>,
+) -> Result<Vec<Vec<Cell>>, String> {
     let parts: Vec<&str> = size_str.split('x').collect();
     if parts.len() != 2 {
         return Err("Invalid size format. Use WxH (e.g., 8x4)".to_string());
@@ -106,12 +517,9 @@ fn generate_map(size_str: String) -> Result<Vec<Vec<u8>>, String> {
 
     let width: usize = parts[0].parse().map_err(|_| "Invalid width")?;
     let height: usize = parts[1].parse().map_err(|_| "Invalid height")?;
-
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let seed = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos() as u64;
+    if width == 0 || height == 0 {
+        return Err("Width and height must be at least 1".to_string());
+    }
 
     let mut rng = seed;
     let mut grid = Vec::new();
@@ -121,34 +529,93 @@ fn generate_map(size_str: String) -> Result<Vec<Vec<u8>>, String> {
         for _ in 0..width {
             rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
             let val = ((rng >> 16) & 0xFF) as u8;
-            row.push(val);
+            let cell = match density {
+                None => Some(val),
+                Some(density) => {
+                    rng = rng.wrapping_mul(1103515245).wrapping_add(12345);
+                    let roll = ((rng >> 16) & 0xFFFF) as f64 / 65536.0;
+                    if roll < density / 2.0 {
+                        None
+                    } else if roll < density {
+                        Some(0xC0 + val / 4)
+                    } else {
+                        Some((val as u16 * 0xC0 / 0x100) as u8)
+                    }
+                }
+            };
+            row.push(cell.map(u32::from));
         }
         grid.push(row);
     }
 
     // Ensure start is 00 and end is FF
     if !grid.is_empty() && !grid[0].is_empty() {
-        grid[0][0] = 0x00;
+        grid[0][0] = Some(0x00);
         let h = grid.len();
         let w = grid[0].len();
-        grid[h - 1][w - 1] = 0xFF;
+        grid[h - 1][w - 1] = Some(0xFF);
     }
 
     Ok(grid)
 }
 
-fn save_map(grid: &[Vec<u8>], filename: &str) -> io::Result<()> {
+/// A cell as written in map files: two hex digits, or `XX` for a wall.
+fn cell_text(cell: Cell) -> String {
+    match cell {
+        Some(val) => format!("{:02X}", val),
+        None => "XX".to_string(),
+    }
+}
+
+fn save_map(grid: &[Vec<Cell>], filename: &str, format: MapFormat) -> io::Result<()> {
+    fs::write(filename, map_text(grid, format))
+}
+
+/// The grid as a map file in `format`.
+fn map_text(grid: &[Vec<Cell>], format: MapFormat) -> String {
+    let separator = match format {
+        MapFormat::Space => ' ',
+        MapFormat::Csv => ',',
+    };
     let mut content = String::new();
     for row in grid {
-        for (i, &val) in row.iter().enumerate() {
+        for (i, &cell) in row.iter().enumerate() {
             if i > 0 {
-                content.push(' ');
+                content.push(separator);
             }
-            content.push_str(&format!("{:02X}", val));
+            content.push_str(&cell_text(cell));
         }
         content.push('\n');
     }
-    fs::write(filename, content)
+    content
+}
+
+/// Lowest and highest value the color gradient spreads over.
+type ColorScale = (u64, u64);
+
+/// The gradient of one-byte maps and of values already brought into 00-FF.
+const BYTE_SCALE: ColorScale = (0, 0xFF);
+
+/// The values the colors spread over: 00-FF for a one-byte map, or the
+/// observed lowest to highest cell of a --wide map, so that values bunched
+/// far above FF still get distinct colors.
+fn color_scale(grid: &[Vec<Cell>]) -> ColorScale {
+    let values = grid.iter().flatten().flatten().copied();
+    let (Some(min), Some(max)) = (values.clone().min(), values.max()) else {
+        return BYTE_SCALE;
+    };
+    if max <= 0xFF {
+        BYTE_SCALE
+    } else {
+        (min.into(), max.into())
+    }
+}
+
+/// `val` brought into 00-FF for `get_color_code`, relative to `scale`.
+fn color_level(val: u64, scale: ColorScale) -> u8 {
+    let (low, high) = scale;
+    let span = (high - low).max(1) as u128;
+    ((val.clamp(low, high) - low) as u128 * 0xFF / span) as u8
 }
 
 fn get_color_code(val: u8) -> u8 {
@@ -164,267 +631,1068 @@ fn get_color_code(val: u8) -> u8 {
     }
 }
 
-fn visualize_map(grid: &[Vec<u8>], path: &[(usize, usize)], max_path: Option<&[(usize, usize)]>) {
+/// The SVG fill matching a terminal color from `get_color_code`.
+fn svg_fill(val: u8) -> &'static str {
+    match get_color_code(val) {
+        1 => "#e53935",
+        3 => "#fdd835",
+        2 => "#43a047",
+        6 => "#00acc1",
+        4 => "#1e88e5",
+        5 => "#8e24aa",
+        _ => "#f5f5f5",
+    }
+}
+
+/// The grid as an SVG image: cells filled with the terminal gradient and
+/// labelled with their value, walls dark grey, the minimum path outlined in
+/// black and the maximum path in dashed red. Cells shrink so large grids stay
+/// within about 1000 pixels; values are left out once cells get too small to
+/// read. On a hex grid odd rows are drawn half a cell to the right.
+fn render_svg(
+    grid: &[Vec<Cell>],
+    min_path: Option<&[(usize, usize)]>,
+    max_path: Option<&[(usize, usize)]>,
+    hex_grid: bool,
+) -> String {
+    let (height, width) = (grid.len(), grid[0].len());
+    let size = (1000 / width.max(height)).clamp(4, 32);
+    let left = |y: usize, x: usize| x * size + if hex_grid && y % 2 == 1 { size / 2 } else { 0 };
+    let scale = color_scale(grid);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+         font-family=\"monospace\" font-size=\"{}\" text-anchor=\"middle\">\n",
+        width * size + if hex_grid && height > 1 { size / 2 } else { 0 },
+        height * size,
+        size * 2 / 5
+    );
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            let fill = cell.map_or("#424242", |val| svg_fill(color_level(val.into(), scale)));
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                left(y, x),
+                y * size,
+                size,
+                size,
+                fill
+            ));
+            if size >= 16 {
+                svg.push_str(&format!(
+                    "<text x=\"{}\" y=\"{}\" dominant-baseline=\"central\">{}</text>\n",
+                    left(y, x) + size / 2,
+                    y * size + size / 2,
+                    cell_text(cell)
+                ));
+            }
+        }
+    }
+    let outlines = [
+        (min_path, "#000000", ""),
+        (max_path, "#d50000", " stroke-dasharray=\"4 2\""),
+    ];
+    for (path, stroke, dash) in outlines {
+        for &(y, x) in path.unwrap_or_default() {
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"{}\" stroke-width=\"{}\"{}/>\n",
+                left(y, x) + 1,
+                y * size + 1,
+                size - 2,
+                size - 2,
+                stroke,
+                (size / 8).max(1),
+                dash
+            ));
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// `text` wrapped in the ANSI color `code`, or unchanged without `color`.
+fn paint(text: &str, code: &str, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// A cell in its gradient color over `scale`; walls are dark grey.
+fn colored_cell(cell: Cell, scale: ColorScale, color: bool) -> String {
+    match cell {
+        Some(val) => paint(
+            &cell_text(cell),
+            &format!("3{}", get_color_code(color_level(val.into(), scale))),
+            color,
+        ),
+        None => paint("XX", "90", color),
+    }
+}
+
+/// A cell of a path view: path cells in `code`, the rest in their gradient
+/// color. Without colors the path is told apart by blanking every other open
+/// cell to `..`.
+fn path_cell(cell: Cell, on_path: bool, code: &str, scale: ColorScale, color: bool) -> String {
+    match (on_path, color) {
+        (true, _) => paint(&cell_text(cell), code, color),
+        (false, true) => colored_cell(cell, scale, color),
+        (false, false) if cell.is_some() => "..".to_string(),
+        (false, false) => cell_text(cell),
+    }
+}
+
+fn visualize_map(
+    grid: &[Vec<Cell>],
+    path: &[(usize, usize)],
+    max_path: Option<&[(usize, usize)]>,
+    color: bool,
+    labels: bool,
+    arrows: bool,
+    hex_grid: bool,
+) {
+    let scale = color_scale(grid);
+    let label_width = (grid.len() - 1).to_string().len();
+    let column_labels = || {
+        if labels {
+            print!("{:width$} ", "", width = label_width);
+            for x in 0..grid[0].len() {
+                print!("{:>2} ", x % 100);
+            }
+            println!();
+        }
+    };
+    let row_label = |y: usize| {
+        if labels {
+            print!("{:>width$} ", y, width = label_width);
+        }
+        print!("{}", row_indent(y, hex_grid));
+    };
+
     let path_set: HashMap<(usize, usize), bool> = path.iter().map(|&p| (p, true)).collect();
+    let path_arrows: HashMap<(usize, usize), char> = if arrows {
+        path.windows(2)
+            .map(|w| (w[0], arrow(w[0], w[1], hex_grid)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
     let max_path_set: HashMap<(usize, usize), bool> = max_path
         .map(|p| p.iter().map(|&pos| (pos, true)).collect())
         .unwrap_or_default();
 
     // Display full hexadecimal grid
-    println!("\nHEXADECIMAL GRID (rainbow gradient):");
+    if color {
+        println!("\nHEXADECIMAL GRID (rainbow gradient):");
+    } else {
+        println!("\nHEXADECIMAL GRID:");
+    }
     println!("==========================================");
     println!();
 
-    for row in grid.iter() {
-        for &val in row.iter() {
-            let color = get_color_code(val);
-            print!("\x1b[3{}m{:02X}\x1b[0m ", color, val);
+    column_labels();
+    for (y, row) in grid.iter().enumerate() {
+        row_label(y);
+        for &cell in row.iter() {
+            print!("{} ", colored_cell(cell, scale, color));
         }
         println!();
     }
 
     // Display minimum path
-    println!("\nMINIMUM COST PATH (shown in WHITE):");
+    if color {
+        println!("\nMINIMUM COST PATH (shown in WHITE):");
+    } else {
+        println!("\nMINIMUM COST PATH:");
+    }
     println!("==========================================");
     println!();
 
+    column_labels();
     for (y, row) in grid.iter().enumerate() {
-        for (x, &val) in row.iter().enumerate() {
-            if path_set.contains_key(&(y, x)) {
-                print!("\x1b[37m{:02X}\x1b[0m ", val); // White
-            } else {
-                let color = get_color_code(val);
-                print!("\x1b[3{}m{:02X}\x1b[0m ", color, val);
+        row_label(y);
+        for (x, &cell) in row.iter().enumerate() {
+            let on_path = path_set.contains_key(&(y, x));
+            if let Some(arrow) = path_arrows.get(&(y, x)) {
+                print!("{} ", paint(&format!("{} ", arrow), "37", color));
+                continue;
             }
+            print!("{} ", path_cell(cell, on_path, "37", scale, color)); // White
         }
         println!();
     }
 
     // Display maximum path if present
     if max_path.is_some() {
-        println!("\nMAXIMUM COST PATH (shown in RED):");
+        if color {
+            println!("\nMAXIMUM COST PATH (shown in RED):");
+        } else {
+            println!("\nMAXIMUM COST PATH:");
+        }
         println!("==========================================");
         println!();
 
+        column_labels();
         for (y, row) in grid.iter().enumerate() {
-            for (x, &val) in row.iter().enumerate() {
-                if max_path_set.contains_key(&(y, x)) {
-                    print!("\x1b[31m{:02X}\x1b[0m ", val); // Red
-                } else {
-                    let color = get_color_code(val);
-                    print!("\x1b[3{}m{:02X}\x1b[0m ", color, val);
-                }
+            row_label(y);
+            for (x, &cell) in row.iter().enumerate() {
+                let on_path = max_path_set.contains_key(&(y, x));
+                print!("{} ", path_cell(cell, on_path, "31", scale, color)); // Red
             }
             println!();
         }
     }
 }
 
-fn animate_pathfinding(
-    grid: &[Vec<u8>],
-    step: usize,
-    current_pos: (usize, usize),
-    cost: u32,
+/// One animation frame: walls `[#]`, the current path `[√]`, other expanded
+/// cells `[*]`.
+fn print_frame(
+    grid: &[Vec<Cell>],
     visited: &[(usize, usize)],
     current_path: &[(usize, usize)],
+    color: bool,
+    hex_grid: bool,
 ) {
     let visited_set: HashMap<(usize, usize), bool> = visited.iter().map(|&p| (p, true)).collect();
     let path_set: HashMap<(usize, usize), bool> = current_path.iter().map(|&p| (p, true)).collect();
 
-    println!(
-        "\nStep {}: Exploring ({},{}) - cost: {}",
-        step, current_pos.1, current_pos.0, cost
-    );
-
     for (y, row) in grid.iter().enumerate() {
-        for (x, _val) in row.iter().enumerate() {
-            if path_set.contains_key(&(y, x)) {
-                print!("[\x1b[32m√\x1b[0m]");
+        print!("{}", row_indent(y, hex_grid));
+        for (x, cell) in row.iter().enumerate() {
+            if cell.is_none() {
+                print!("[#]");
+            } else if path_set.contains_key(&(y, x)) {
+                print!("[{}]", paint("√", "32", color));
             } else if visited_set.contains_key(&(y, x)) {
-                print!("[\x1b[33m*\x1b[0m]");
+                print!("[{}]", paint("*", "33", color));
             } else {
                 print!("[ ]");
             }
         }
         println!();
     }
+}
+
+fn animate_pathfinding(
+    grid: &[Vec<Cell>],
+    step: usize,
+    current_pos: (usize, usize),
+    cost: u64,
+    visited: &[(usize, usize)],
+    current_path: &[(usize, usize)],
+    opts: &SearchOptions,
+) {
+    println!(
+        "\nStep {}: Exploring ({},{}) - cost: {}",
+        step, current_pos.1, current_pos.0, cost
+    );
+    print_frame(grid, visited, current_path, opts.color, opts.hex_grid);
 
     io::stdout().flush().unwrap();
-    thread::sleep(Duration::from_millis(100));
+    thread::sleep(opts.animate_delay);
+}
+
+/// A path from start to end and its total cost.
+type Path = (Vec<(usize, usize)>, u64);
+
+/// The minimum cost path, or `None` when the end cannot be reached, every
+/// cell expanded by the search in order, and how many distinct paths share
+/// the minimum cost (only counted with `count_paths`, otherwise 1), and the
+/// cost to reach each cell as the search left it (`u64::MAX` if unreached),
+/// and whether the search ran out of time, in which case no path is returned.
+type PathResult = (Option<Path>, Vec<(usize, usize)>, u64, Vec<Vec<u64>>, bool);
+
+/// Expanded cells between checks of the search timeout.
+const TIMEOUT_CHECK_EVERY: usize = 1024;
+
+/// Passable cells one step away from `pos`, with the cost of entering them:
+/// up, down, left, right, plus the four diagonals with `diagonals`. A
+/// diagonal step costs the value of the cell it enters, like any other step.
+/// With `hex_grid` odd rows sit half a cell right, so a cell touches the two
+/// cells above and below it on its own side: up-left and down-left on even
+/// rows, up-right and down-right on odd rows. Walls are never returned.
+fn neighbors(
+    grid: &[Vec<Cell>],
+    pos: (usize, usize),
+    opts: &SearchOptions,
+) -> Vec<((usize, usize), u32)> {
+    let (height, width) = (grid.len(), grid[0].len());
+    let (y, x) = pos;
+    // Column offset of the one diagonal each hex row pair keeps.
+    let hex_dx = if y % 2 == 0 { -1 } else { 1 };
+    let mut neighbors = Vec::new();
+    for dy in -1isize..=1 {
+        for dx in -1isize..=1 {
+            let diagonal = dy != 0 && dx != 0;
+            let allowed = if opts.hex_grid {
+                !diagonal || dx == hex_dx
+            } else {
+                !diagonal || opts.diagonals
+            };
+            if (dy == 0 && dx == 0) || !allowed {
+                continue;
+            }
+            let (Some(ny), Some(nx)) = (y.checked_add_signed(dy), x.checked_add_signed(dx)) else {
+                continue;
+            };
+            if ny >= height || nx >= width {
+                continue;
+            }
+            if let Some(val) = grid[ny][nx] {
+                neighbors.push(((ny, nx), val));
+            }
+        }
+    }
+    neighbors
 }
 
-type PathResult = (Vec<(usize, usize)>, u32, Vec<(usize, usize)>);
+/// A* estimate of the cost still to pay from `pos` to `end`: at least
+/// Manhattan-distance steps remain (Chebyshev distance with diagonals, hex
+/// distance on a hex grid), each entering a cell worth at least `min_cell`,
+/// so the estimate never exceeds the true cost (admissible) and A* still
+/// returns a minimum cost path.
+fn heuristic(pos: (usize, usize), end: (usize, usize), min_cell: u64, opts: &SearchOptions) -> u64 {
+    let (dy, dx) = (pos.0.abs_diff(end.0), pos.1.abs_diff(end.1));
+    let steps = if opts.hex_grid {
+        let ((pq, pr), (eq, er)) = (axial(pos), axial(end));
+        let (dq, dr) = (pq - eq, pr - er);
+        (dq.abs() + dr.abs() + (dq + dr).abs()) as usize / 2
+    } else if opts.diagonals {
+        dy.max(dx)
+    } else {
+        dy + dx
+    };
+    steps as u64 * min_cell
+}
+
+/// For each cell and search slot, the cell and slot the search came from.
+type Links = Vec<Vec<Vec<Option<((usize, usize), usize)>>>>;
+
+/// The path from the start to `pos` (entered in `slot`), following `prev`.
+fn trace(prev: &Links, pos: (usize, usize), slot: usize) -> Vec<(usize, usize)> {
+    let mut path = Vec::new();
+    let mut curr = Some((pos, slot));
+    while let Some(((y, x), slot)) = curr {
+        path.push((y, x));
+        curr = prev[y][x][slot];
+    }
+    path.reverse();
+    path
+}
 
-fn dijkstra_min(grid: &[Vec<u8>], animate: bool) -> PathResult {
+fn dijkstra_min(grid: &[Vec<Cell>], opts: &SearchOptions) -> PathResult {
+    let animate = opts.animate;
     let height = grid.len();
     let width = grid[0].len();
-    let mut dist = vec![vec![u32::MAX; width]; height];
-    let mut prev = vec![vec![None; width]; height];
+    let (start, end) = (opts.start, opts.end);
+    let min_cell = match opts.algorithm {
+        Algorithm::Dijkstra => 0,
+        // The start cell is left out: a minimum cost path never re-enters it.
+        Algorithm::Astar => (0..height)
+            .flat_map(|y| (0..width).map(move |x| (y, x)))
+            .filter(|&pos| pos != start)
+            .filter_map(|(y, x)| grid[y][x])
+            .min()
+            .map_or(0, |min| opts.extend(0, min)),
+    };
+    let estimate = |pos| {
+        if opts.bottleneck {
+            // Remaining steps do not add up, so the sum estimate would
+            // overshoot; the search runs as plain Dijkstra.
+            return 0;
+        }
+        let h = heuristic(pos, end, min_cell, opts) as f64;
+        (h * opts.heuristic_weight).round() as u64
+    };
+    // With a turn penalty the cost of leaving a cell depends on how it was
+    // entered, so each cell is searched once per incoming direction. Without
+    // one, every direction shares a single slot.
+    let slots = if opts.turn_penalty > 0 { 9 } else { 1 };
+    let slot = |dir: usize| if slots == 1 { 0 } else { dir };
+    let mut best = vec![vec![vec![u64::MAX; slots]; width]; height];
+    let mut prev: Links = vec![vec![vec![None; slots]; width]; height];
+    let mut dist = vec![vec![u64::MAX; width]; height];
     let mut heap = BinaryHeap::new();
     let mut visited_order = Vec::new();
+    let timer = Instant::now();
 
-    dist[0][0] = grid[0][0] as u32;
+    let start_cost = opts.start_cost(grid[start.0][start.1].unwrap_or(0));
+    dist[start.0][start.1] = start_cost;
+    best[start.0][start.1][slot(NO_DIRECTION)] = start_cost;
     heap.push(State {
-        cost: grid[0][0] as u32,
-        pos: (0, 0),
+        cost: start_cost,
+        priority: start_cost.saturating_add(estimate(start)),
+        pos: start,
+        dir: NO_DIRECTION,
     });
 
-    while let Some(State { cost, pos }) = heap.pop() {
+    while let Some(State {
+        cost,
+        priority,
+        pos,
+        dir,
+    }) = heap.pop()
+    {
         let (y, x) = pos;
 
-        if cost > dist[y][x] {
+        if cost > best[y][x][slot(dir)] {
             continue;
         }
+        // Once the end is reached, only cells that can still lie on an
+        // equally cheap path matter for the count.
+        if priority > dist[end.0][end.1] {
+            break;
+        }
 
         visited_order.push(pos);
 
+        if visited_order.len() % TIMEOUT_CHECK_EVERY == 0
+            && opts
+                .timeout
+                .is_some_and(|timeout| timer.elapsed() > timeout)
+        {
+            return (None, visited_order, 0, dist, true);
+        }
+
         if animate
             && (visited_order.len() == 1
                 || visited_order.len() == 2
-                || visited_order.len() % 10 == 0)
+                || visited_order.len() % opts.animate_every == 0)
         {
-            let mut path = Vec::new();
-            let mut curr = Some(pos);
-            while let Some(p) = curr {
-                path.push(p);
-                curr = prev[p.0][p.1];
-            }
-            path.reverse();
-            animate_pathfinding(grid, visited_order.len(), pos, cost, &visited_order, &path);
+            let path = trace(&prev, pos, slot(dir));
+            animate_pathfinding(
+                grid,
+                visited_order.len(),
+                pos,
+                cost,
+                &visited_order,
+                &path,
+                opts,
+            );
         }
 
-        if y == height - 1 && x == width - 1 {
+        if pos == end {
             if animate {
-                let mut path = Vec::new();
-                let mut curr = Some(pos);
-                while let Some(p) = curr {
-                    path.push(p);
-                    curr = prev[p.0][p.1];
-                }
-                path.reverse();
+                let path = trace(&prev, pos, slot(dir));
                 println!("\nStep {}: Path found!", visited_order.len());
-                for (y_grid, row) in grid.iter().enumerate() {
-                    for (x_grid, _val) in row.iter().enumerate() {
-                        if path.contains(&(y_grid, x_grid)) {
-                            print!("[\x1b[32m√\x1b[0m]");
-                        } else if visited_order.contains(&(y_grid, x_grid)) {
-                            print!("[\x1b[33m*\x1b[0m]");
-                        } else {
-                            print!("[ ]");
-                        }
-                    }
-                    println!();
-                }
+                print_frame(grid, &visited_order, &path, opts.color, opts.hex_grid);
+            }
+            if opts.count_paths {
+                continue;
             }
             break;
         }
 
-        let mut neighbors = Vec::new();
-        if y > 0 {
-            neighbors.push((y - 1, x));
-        }
-        if y + 1 < height {
-            neighbors.push((y + 1, x));
-        }
-        if x > 0 {
-            neighbors.push((y, x - 1));
-        }
-        if x + 1 < width {
-            neighbors.push((y, x + 1));
-        }
-
-        for &(ny, nx) in &neighbors {
-            let new_cost = cost + grid[ny][nx] as u32;
+        for ((ny, nx), step) in neighbors(grid, pos, opts) {
+            let new_dir = direction(pos, (ny, nx), opts.hex_grid);
+            let mut new_cost = opts.extend(cost, step);
+            if dir != NO_DIRECTION && new_dir != dir {
+                new_cost += u64::from(opts.turn_penalty);
+            }
 
-            if new_cost < dist[ny][nx] {
-                dist[ny][nx] = new_cost;
-                prev[ny][nx] = Some((y, x));
+            if new_cost < best[ny][nx][slot(new_dir)] {
+                best[ny][nx][slot(new_dir)] = new_cost;
+                prev[ny][nx][slot(new_dir)] = Some((pos, slot(dir)));
+                dist[ny][nx] = dist[ny][nx].min(new_cost);
                 heap.push(State {
                     cost: new_cost,
+                    priority: new_cost.saturating_add(estimate((ny, nx))),
                     pos: (ny, nx),
+                    dir: new_dir,
                 });
             }
         }
     }
 
+    if dist[end.0][end.1] == u64::MAX {
+        return (None, visited_order, 0, dist, false);
+    }
+
+    let end_slot = (0..slots)
+        .min_by_key(|&slot| best[end.0][end.1][slot])
+        .unwrap_or(0);
+    let path = trace(&prev, end, end_slot);
+
+    let count = if opts.count_paths {
+        count_min_paths(grid, &dist, &visited_order, opts)
+    } else {
+        1
+    };
+    let cost = dist[end.0][end.1];
+    (Some((path, cost)), visited_order, count, dist, false)
+}
+
+/// Maximum cost path that only moves right or down (and down-right with
+/// diagonals). Straight down is a hex neighbor on either row parity, so the
+/// same moves serve a hex grid. The longest simple path on a grid with
+/// cycles is NP-hard, but with monotone moves the grid is acyclic, so one
+/// pass in row-major order finds the exact maximum. `None` when the end is
+/// not below and to the right of the start, or walls block every monotone
+/// route.
+fn max_path_monotone(grid: &[Vec<Cell>], opts: &SearchOptions) -> Option<Path> {
+    let (start, end) = (opts.start, opts.end);
+    if end.0 < start.0 || end.1 < start.1 {
+        return None;
+    }
+    let width = grid[0].len();
+    let mut best: Vec<Vec<Option<u64>>> = vec![vec![None; width]; grid.len()];
+    let mut prev = vec![vec![None; width]; grid.len()];
+
+    for y in start.0..=end.0 {
+        for x in start.1..=end.1 {
+            let Some(val) = grid[y][x] else {
+                continue;
+            };
+            if (y, x) == start {
+                best[y][x] = Some(opts.start_cost(val));
+                continue;
+            }
+            let mut from = Vec::new();
+            if y > start.0 {
+                from.push((y - 1, x));
+            }
+            if x > start.1 {
+                from.push((y, x - 1));
+            }
+            if opts.diagonals && y > start.0 && x > start.1 {
+                from.push((y - 1, x - 1));
+            }
+            if let Some((cost, p)) = from
+                .into_iter()
+                .filter_map(|p| best[p.0][p.1].map(|cost| (cost, p)))
+                .max_by_key(|&(cost, _)| cost)
+            {
+                best[y][x] = Some(cost + u64::from(val));
+                prev[y][x] = Some(p);
+            }
+        }
+    }
+
+    let cost = best[end.0][end.1]?;
     let mut path = Vec::new();
-    let mut curr = Some((height - 1, width - 1));
+    let mut curr = Some(end);
     while let Some(pos) = curr {
         path.push(pos);
         curr = prev[pos.0][pos.1];
     }
     path.reverse();
 
-    (path, dist[height - 1][width - 1], visited_order)
+    Some((path, cost))
 }
 
-fn dijkstra_max(grid: &[Vec<u8>]) -> (Vec<(usize, usize)>, u32) {
-    let height = grid.len();
-    let width = grid[0].len();
-    let mut dist = vec![vec![0u32; width]; height];
-    let mut prev = vec![vec![None; width]; height];
-    let mut visited = vec![vec![false; width]; height];
+/// Cheapest cost to reach every cell from the start, or `None` for cells
+/// whose cheapest path costs more than `budget`. The same relaxation as
+/// `dijkstra_min`, without a target: cells over budget are never expanded.
+fn reachable_within(
+    grid: &[Vec<Cell>],
+    opts: &SearchOptions,
+    budget: u64,
+) -> Vec<Vec<Option<u64>>> {
+    let start = opts.start;
+    let mut dist = vec![vec![None; grid[0].len()]; grid.len()];
     let mut heap = BinaryHeap::new();
 
-    dist[0][0] = grid[0][0] as u32;
-    heap.push(StateMax {
-        cost: grid[0][0] as u32,
-        pos: (0, 0),
-    });
-
-    while let Some(StateMax { cost, pos }) = heap.pop() {
-        let (y, x) = pos;
+    let start_cost = opts.start_cost(grid[start.0][start.1].unwrap_or(0));
+    if start_cost <= budget {
+        dist[start.0][start.1] = Some(start_cost);
+        heap.push(State {
+            cost: start_cost,
+            priority: start_cost,
+            pos: start,
+            dir: NO_DIRECTION,
+        });
+    }
 
-        if visited[y][x] {
+    while let Some(State { cost, pos, .. }) = heap.pop() {
+        if dist[pos.0][pos.1].is_some_and(|d| cost > d) {
             continue;
         }
-        visited[y][x] = true;
-
-        if y == height - 1 && x == width - 1 {
-            break;
+        for ((ny, nx), step) in neighbors(grid, pos, opts) {
+            let new_cost = opts.extend(cost, step);
+            if new_cost <= budget && dist[ny][nx].is_none_or(|d| new_cost < d) {
+                dist[ny][nx] = Some(new_cost);
+                heap.push(State {
+                    cost: new_cost,
+                    priority: new_cost,
+                    pos: (ny, nx),
+                    dir: NO_DIRECTION,
+                });
+            }
         }
+    }
+    dist
+}
 
-        let mut neighbors = Vec::new();
-        if y > 0 {
-            neighbors.push((y - 1, x));
-        }
-        if y + 1 < height {
-            neighbors.push((y + 1, x));
-        }
-        if x > 0 {
-            neighbors.push((y, x - 1));
+/// Cheapest path from `spur` to the end that avoids the `blocked` cells and
+/// does not leave `spur` towards any cell of `banned`, with the cost of the
+/// whole path given that reaching `spur` already cost `cost`.
+fn spur_path(
+    grid: &[Vec<Cell>],
+    opts: &SearchOptions,
+    spur: (usize, usize),
+    cost: u64,
+    blocked: &[Vec<bool>],
+    banned: &HashSet<(usize, usize)>,
+) -> Option<Path> {
+    let end = opts.end;
+    let mut dist = vec![vec![u64::MAX; grid[0].len()]; grid.len()];
+    let mut prev = vec![vec![None; grid[0].len()]; grid.len()];
+    let mut heap = BinaryHeap::new();
+    dist[spur.0][spur.1] = cost;
+    heap.push(State {
+        cost,
+        priority: cost,
+        pos: spur,
+        dir: NO_DIRECTION,
+    });
+
+    while let Some(State { cost, pos, .. }) = heap.pop() {
+        if pos == end {
+            break;
         }
-        if x + 1 < width {
-            neighbors.push((y, x + 1));
+        if cost > dist[pos.0][pos.1] {
+            continue;
         }
-
-        for &(ny, nx) in &neighbors {
-            if visited[ny][nx] {
+        for ((ny, nx), step) in neighbors(grid, pos, opts) {
+            if blocked[ny][nx] || (pos == spur && banned.contains(&(ny, nx))) {
                 continue;
             }
-
-            let new_cost = cost + grid[ny][nx] as u32;
-
-            if new_cost > dist[ny][nx] {
+            let new_cost = opts.extend(cost, step);
+            if new_cost < dist[ny][nx] {
                 dist[ny][nx] = new_cost;
-                prev[ny][nx] = Some((y, x));
-                heap.push(StateMax {
+                prev[ny][nx] = Some(pos);
+                heap.push(State {
                     cost: new_cost,
+                    priority: new_cost,
                     pos: (ny, nx),
+                    dir: NO_DIRECTION,
                 });
             }
         }
     }
 
+    if dist[end.0][end.1] == u64::MAX {
+        return None;
+    }
     let mut path = Vec::new();
-    let mut curr = Some((height - 1, width - 1));
+    let mut curr = Some(end);
     while let Some(pos) = curr {
         path.push(pos);
         curr = prev[pos.0][pos.1];
     }
     path.reverse();
+    Some((path, dist[end.0][end.1]))
+}
+
+/// Up to `k` cheapest loop-free paths from start to end, cheapest first
+/// (Yen's algorithm). Each found path is taken apart at every cell, the spur:
+/// the next path must follow it up to the spur, then leave it along a step no
+/// found path with the same beginning took, without revisiting the
+/// beginning. The cheapest of these candidates is the next path.
+fn k_shortest_paths(grid: &[Vec<Cell>], opts: &SearchOptions, k: usize) -> Vec<Path> {
+    let quiet = SearchOptions {
+        animate: false,
+        count_paths: false,
+        ..*opts
+    };
+    let Some(first) = dijkstra_min(grid, &quiet).0 else {
+        return Vec::new();
+    };
+    let mut found = vec![first];
+    let mut seen: HashSet<Vec<(usize, usize)>> = HashSet::from([found[0].0.clone()]);
+    let mut candidates = BinaryHeap::new();
+
+    while found.len() < k {
+        let last = found[found.len() - 1].0.clone();
+        let mut cost = 0;
+        for i in 0..last.len() - 1 {
+            let (y, x) = last[i];
+            let val = grid[y][x].unwrap_or(0);
+            cost = if i == 0 {
+                opts.start_cost(val)
+            } else {
+                opts.extend(cost, val)
+            };
+            let root = &last[..=i];
+            let banned: HashSet<(usize, usize)> = found
+                .iter()
+                .filter(|(path, _)| path.len() > i + 1 && path[..=i] == *root)
+                .map(|(path, _)| path[i + 1])
+                .collect();
+            let mut blocked = vec![vec![false; grid[0].len()]; grid.len()];
+            for &(y, x) in &root[..i] {
+                blocked[y][x] = true;
+            }
+            let Some((spur, total)) = spur_path(grid, opts, last[i], cost, &blocked, &banned)
+            else {
+                continue;
+            };
+            let path = [&root[..i], &spur[..]].concat();
+            if seen.insert(path.clone()) {
+                candidates.push(std::cmp::Reverse((total, path)));
+            }
+        }
+        let Some(std::cmp::Reverse((cost, path))) = candidates.pop() else {
+            break;
+        };
+        found.push((path, cost));
+    }
+    found
+}
+
+/// Cells connected to the start by steps between open cells, found with a
+/// breadth-first flood fill over the same neighbors as the searches.
+fn connected_cells(grid: &[Vec<Cell>], opts: &SearchOptions) -> Vec<Vec<bool>> {
+    let start = opts.start;
+    let mut seen = vec![vec![false; grid[0].len()]; grid.len()];
+    let mut queue = VecDeque::from([start]);
+    seen[start.0][start.1] = true;
+    while let Some(pos) = queue.pop_front() {
+        for ((ny, nx), _) in neighbors(grid, pos, opts) {
+            if !seen[ny][nx] {
+                seen[ny][nx] = true;
+                queue.push_back((ny, nx));
+            }
+        }
+    }
+    seen
+}
+
+/// Unreachable cells listed by `--connectivity` before the rest are counted.
+const UNREACHABLE_SHOWN: usize = 10;
+
+/// Largest `--k-paths` allowed on `grid`. Every path found runs a search
+/// per cell of the previous path, so K shrinks as the grid grows; small
+/// grids stop at 100.
+fn k_paths_cap(grid: &[Vec<Cell>]) -> usize {
+    let (height, width) = (grid.len(), grid[0].len());
+    (10_000_000 / (height * width * (height + width))).clamp(1, 100)
+}
+
+/// The map with cells outside the reachable region blanked to `..`.
+fn print_reachable_map(grid: &[Vec<Cell>], reach: &[Vec<Option<u64>>], opts: &SearchOptions) {
+    let color = opts.color;
+    let scale = color_scale(grid);
+    println!("\nREACHABLE REGION:");
+    println!("==========================================");
+    println!();
+    for (y, (row, reach_row)) in grid.iter().zip(reach).enumerate() {
+        print!("{}", row_indent(y, opts.hex_grid));
+        for (&cell, reached) in row.iter().zip(reach_row) {
+            print!(
+                "{} ",
+                path_cell(cell, reached.is_some(), "32", scale, color)
+            );
+        }
+        println!();
+    }
+}
+
+/// The cost-to-reach field of the minimum cost search, scaled to 00-FF
+/// (the farthest reached cell is FF) and colored with the map gradient.
+/// Cells the search never reached show `..`.
+fn print_heatmap(grid: &[Vec<Cell>], dist: &[Vec<u64>], opts: &SearchOptions) {
+    let color = opts.color;
+    let max = dist
+        .iter()
+        .flatten()
+        .copied()
+        .filter(|&d| d != u64::MAX)
+        .max()
+        .unwrap_or(0);
+    println!(
+        "\nDISTANCE HEAT MAP (cost to reach, scaled to 00-FF; FF = {}):",
+        max
+    );
+    println!("==========================================");
+    println!();
+    for (y, (row, dist_row)) in grid.iter().zip(dist).enumerate() {
+        print!("{}", row_indent(y, opts.hex_grid));
+        for (&cell, &d) in row.iter().zip(dist_row) {
+            if cell.is_none() {
+                print!("{} ", colored_cell(cell, BYTE_SCALE, color));
+            } else if d == u64::MAX {
+                print!(".. ");
+            } else {
+                let level = color_level(d, (0, max));
+                print!("{} ", colored_cell(Some(level.into()), BYTE_SCALE, color));
+            }
+        }
+        println!();
+    }
+}
+
+/// Number of distinct minimum cost paths from start to end, saturating at
+/// `u64::MAX`. Counting while relaxing would need every cheaper predecessor
+/// expanded before a cell, which A* tie-breaking does not guarantee, so the
+/// count is taken afterwards over the expanded cells, whose `dist` is final:
+/// each cell is reached by the sum of the counts of its neighbors that lead
+/// to it at exactly its cost.
+///
+/// The count assumes positive cells. Entering a 00 cell leaves the cost
+/// unchanged, so neighbors tie on `dist` and only the ones ordered first
+/// count as predecessors: every path counted is still a distinct minimum
+/// cost path, but zero-cost detours are missed and the count is a lower
+/// bound that depends on the expansion order (an all-00 3x3 map reports 6
+/// of its 12 paths).
+fn count_min_paths(
+    grid: &[Vec<Cell>],
+    dist: &[Vec<u64>],
+    expanded: &[(usize, usize)],
+    opts: &SearchOptions,
+) -> u64 {
+    let mut order = expanded.to_vec();
+    order.sort_by_key(|&(y, x)| dist[y][x]);
+    let mut expanded_set = vec![vec![false; grid[0].len()]; grid.len()];
+    for &(y, x) in &order {
+        expanded_set[y][x] = true;
+    }
+
+    let mut ways = vec![vec![0u64; grid[0].len()]; grid.len()];
+    ways[opts.start.0][opts.start.1] = 1;
+    for &(y, x) in &order {
+        if (y, x) == opts.start {
+            continue;
+        }
+        let enter = u64::from(grid[y][x].unwrap_or(0));
+        for ((ny, nx), _) in neighbors(grid, (y, x), opts) {
+            if expanded_set[ny][nx] && dist[ny][nx] + enter == dist[y][x] {
+                ways[y][x] = ways[y][x].saturating_add(ways[ny][nx]);
+            }
+        }
+    }
+    ways[opts.end.0][opts.end.1]
+}
+
+fn print_expansions(opts: &SearchOptions, expanded: usize) {
+    match opts.algorithm {
+        Algorithm::Dijkstra => println!("Nodes expanded: {} (Dijkstra)", expanded),
+        Algorithm::Astar if opts.heuristic_weight == 1.0 => {
+            println!("Nodes expanded: {} (A*)", expanded)
+        }
+        Algorithm::Astar => println!(
+            "Nodes expanded: {} (A*, heuristic weight {})",
+            expanded, opts.heuristic_weight
+        ),
+    }
+    if opts.algorithm == Algorithm::Astar {
+        if opts.optimal() {
+            println!("Optimal: guaranteed");
+        } else {
+            println!("Optimal: not guaranteed (heuristic weight above 1)");
+        }
+    }
+}
+
+/// Number of direction changes along `path`.
+fn count_turns(path: &[(usize, usize)], hex_grid: bool) -> usize {
+    path.windows(3)
+        .filter(|w| direction(w[0], w[1], hex_grid) != direction(w[1], w[2], hex_grid))
+        .count()
+}
+
+fn print_stats(grid: &[Vec<Cell>], opts: &SearchOptions, path: &[(usize, usize)]) {
+    // Only the cells the cost counts: the start drops out with --exclude-start.
+    let counted = &path[opts.exclude_start as usize..];
+    let values: Vec<u32> = counted.iter().filter_map(|&(y, x)| grid[y][x]).collect();
+    let (min, max) = (
+        values.iter().min().copied().unwrap_or(0),
+        values.iter().max().copied().unwrap_or(0),
+    );
+    let sum: u64 = values.iter().map(|&val| u64::from(val)).sum();
+    println!(
+        "Stats: {} cells{}, average cell {:.2}, min cell {:02X} ({}), max cell {:02X} ({}), {} direction changes",
+        path.len(),
+        if opts.exclude_start { " (start excluded)" } else { "" },
+        sum as f64 / values.len().max(1) as f64,
+        min,
+        min,
+        max,
+        max,
+        count_turns(path, opts.hex_grid)
+    );
+}
+
+fn print_turns(opts: &SearchOptions, path: &[(usize, usize)]) {
+    if opts.turn_penalty == 0 {
+        return;
+    }
+    let turns = count_turns(path, opts.hex_grid);
+    println!(
+        "Turns: {} (cost includes {} x {} penalty)",
+        turns, turns, opts.turn_penalty
+    );
+}
+
+fn print_path_count(opts: &SearchOptions, count: u64) {
+    if !opts.count_paths {
+        return;
+    }
+    if count == u64::MAX {
+        println!("Minimum cost paths: at least {} (count saturated)", count);
+    } else {
+        println!("Minimum cost paths: {}", count);
+    }
+}
+
+/// The product of the cells on `path` (00 counting as 01): exact while it
+/// fits in a `u128`, otherwise in scientific notation.
+fn product_text(grid: &[Vec<Cell>], path: &[(usize, usize)]) -> String {
+    let values = path
+        .iter()
+        .map(|&(y, x)| grid[y][x].map_or(1, |v| v.max(1)));
+    if let Some(product) = values
+        .clone()
+        .try_fold(1u128, |acc, v| acc.checked_mul(v as u128))
+    {
+        return product.to_string();
+    }
+    let log10: f64 = values.map(|v| (v as f64).log10()).sum();
+    format!("{:.3}e{}", 10f64.powf(log10.fract()), log10.trunc())
+}
+
+/// A path's cost as reported to the user: the product of its cells with the
+/// multiply model (the search only knows the log-sum), `cost` otherwise.
+fn cost_text(
+    grid: &[Vec<Cell>],
+    opts: &SearchOptions,
+    path: &[(usize, usize)],
+    cost: u64,
+) -> String {
+    match opts.cost_model {
+        CostModel::Multiply if !opts.bottleneck => {
+            product_text(grid, &path[opts.exclude_start as usize..])
+        }
+        _ => cost.to_string(),
+    }
+}
+
+/// `{"cost":C,"path":[[x,y],...]}`, or `null` when there is no path.
+/// Coordinates are `[x, y]` like the `(x,y)` of the text output.
+fn path_json(grid: &[Vec<Cell>], opts: &SearchOptions, path: &Option<Path>) -> String {
+    let Some((cells, cost)) = path else {
+        return "null".to_string();
+    };
+    let cost = cost_text(grid, opts, cells, *cost);
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|&(y, x)| format!("[{},{}]", x, y))
+        .collect();
+    format!("{{\"cost\":{},\"path\":[{}]}}", cost, cells.join(","))
+}
+
+/// The search result as one JSON object; `max` is only present with `--both`.
+fn result_json(
+    grid: &[Vec<Cell>],
+    opts: &SearchOptions,
+    min: &Option<Path>,
+    max: Option<&Option<Path>>,
+    path_count: u64,
+    expanded: usize,
+) -> String {
+    let mut json = format!(
+        "{{\"width\":{},\"height\":{},\"start\":[{},{}],\"end\":[{},{}],\"min\":{}",
+        grid[0].len(),
+        grid.len(),
+        opts.start.1,
+        opts.start.0,
+        opts.end.1,
+        opts.end.0,
+        path_json(grid, opts, min)
+    );
+    if opts.count_paths {
+        json.push_str(&format!(",\"min_path_count\":{}", path_count));
+    }
+    json.push_str(&format!(
+        ",\"objective\":\"{}\",\"optimal\":{},\"nodes_expanded\":{}",
+        match (opts.bottleneck, opts.cost_model) {
+            (true, _) => "bottleneck",
+            (false, CostModel::Add) => "sum",
+            (false, CostModel::Multiply) => "product",
+        },
+        opts.optimal(),
+        expanded
+    ));
+    if let Some(max) = max {
+        json.push_str(&format!(",\"max\":{}", path_json(grid, opts, max)));
+    }
+    json.push('}');
+    json
+}
+
+/// Maps tried by `--solvable` before giving up.
+const SOLVABLE_ATTEMPTS: u32 = 100;
 
-    (path, dist[height - 1][width - 1])
+/// Turn cells worth `wall` into walls.
+fn apply_wall_value(grid: Vec<Vec<Cell>>, wall: Option<u32>) -> Vec<Vec<Cell>> {
+    let Some(wall) = wall else {
+        return grid;
+    };
+    grid.into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|cell| cell.filter(|&val| val != wall))
+                .collect()
+        })
+        .collect()
+}
+
+/// Search settings from the command line; start and end default to the top
+/// left and bottom right corners and must be open cells inside the grid.
+fn search_options(args: &Args, grid: &[Vec<Cell>]) -> Result<SearchOptions, String> {
+    let (start, end) = endpoints(args, grid)?;
+    validate_coord(grid, start, "Start")?;
+    validate_coord(grid, end, "End")?;
+
+    Ok(SearchOptions {
+        algorithm: args.algorithm,
+        heuristic_weight: args.heuristic_weight,
+        animate: args.animate,
+        animate_delay: Duration::from_millis(args.animate_delay),
+        animate_every: args.animate_every as usize,
+        color: !args.no_color && io::stdout().is_terminal(),
+        diagonals: args.diagonals,
+        hex_grid: args.hex_grid,
+        start,
+        end,
+        count_paths: args.count_paths,
+        bottleneck: args.bottleneck,
+        exclude_start: args.exclude_start,
+        cost_model: args.cost_model,
+        turn_penalty: args.turn_penalty,
+        timeout: args.timeout.map(Duration::from_millis),
+    })
+}
+
+/// The start and end cells, (row, column), checked to lie inside the grid.
+/// They may still be walls: that depends on the map, not only on its size.
+fn endpoints(args: &Args, grid: &[Vec<Cell>]) -> Result<(Pos, Pos), String> {
+    if grid.is_empty() || grid[0].is_empty() {
+        return Err("Empty map".to_string());
+    }
+    let start = args.start.unwrap_or((0, 0));
+    let end = args
+        .end
+        .unwrap_or((grid.len() - 1, grid[0].len().saturating_sub(1)));
+    check_inside(grid, start, "Start")?;
+    check_inside(grid, end, "End")?;
+    Ok((start, end))
+}
+
+/// Whether the end can be reached from the start, checked with a quiet run
+/// of the minimum cost search. An endpoint on a wall counts as unsolvable,
+/// so another seed is tried.
+fn is_solvable(args: &Args, grid: &[Vec<Cell>]) -> bool {
+    search_options(args, grid).is_ok_and(|opts| {
+        let opts = SearchOptions {
+            animate: false,
+            count_paths: false,
+            timeout: None,
+            ..opts
+        };
+        dijkstra_min(grid, &opts).0.is_some()
+    })
 }
 
 fn main() -> Result<(), String> {
@@ -432,51 +1700,320 @@ fn main() -> Result<(), String> {
 
     let grid = if let Some(ref gen_size) = args.generate {
         let parts: Vec<&str> = gen_size.split('x').collect();
-        println!("Generating {}x{} hexadecimal grid...", parts[0], parts[1]);
-        println!();
-        let grid = generate_map(gen_size.clone())?;
+        let mut seed = args.seed.unwrap_or_else(|| {
+            use std::time::{SystemTime, UNIX_EPOCH};
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64
+        });
+        let mut grid = apply_wall_value(
+            generate_map(gen_size.clone(), seed, args.density)?,
+            args.wall_value,
+        );
+        if args.solvable {
+            // Every attempt has the same size: an endpoint outside the grid
+            // is an error, not a reason to keep regenerating.
+            endpoints(&args, &grid)?;
+        }
+        let mut attempts = 1;
+        while args.solvable && !is_solvable(&args, &grid) {
+            if attempts == SOLVABLE_ATTEMPTS {
+                return Err(format!(
+                    "No solvable map in {} attempts; try a lower --density",
+                    SOLVABLE_ATTEMPTS
+                ));
+            }
+            seed = seed.wrapping_add(1);
+            grid = apply_wall_value(
+                generate_map(gen_size.clone(), seed, args.density)?,
+                args.wall_value,
+            );
+            attempts += 1;
+        }
+        if !args.json {
+            println!("Generating {}x{} hexadecimal grid...", parts[0], parts[1]);
+            if attempts > 1 {
+                println!("Seed: {} (solvable after {} attempts)", seed, attempts);
+            } else {
+                println!("Seed: {}", seed);
+            }
+            println!();
+        }
 
         if let Some(output_file) = &args.output {
-            save_map(&grid, output_file).map_err(|e| format!("Failed to save map: {}", e))?;
-            println!("Map saved to: {}", output_file);
+            save_map(&grid, output_file, args.format)
+                .map_err(|e| format!("Failed to save map: {}", e))?;
+            if !args.json {
+                println!("Map saved to: {}", output_file);
+            }
         }
 
-        println!("Generated Map:");
-        for row in &grid {
-            for &val in row {
-                print!("{:02X} ", val);
+        if !args.json {
+            println!("Generated Map:");
+            for row in &grid {
+                for &cell in row {
+                    print!("{} ", cell_text(cell));
+                }
+                println!();
             }
             println!();
         }
-        println!();
 
         grid
-    } else if let Some(map_file) = args.map_file {
-        let content =
-            fs::read_to_string(&map_file).map_err(|e| format!("Failed to read map file: {}", e))?;
-        parse_map(&content)?
+    } else if let Some(map_file) = &args.map_file {
+        let content = if map_file == "-" {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| format!("Failed to read map from stdin: {}", e))?;
+            if content.trim().is_empty() {
+                return Err("No map data on stdin".to_string());
+            }
+            content
+        } else {
+            fs::read_to_string(map_file).map_err(|e| format!("Failed to read map file: {}", e))?
+        };
+        let grid = parse_map(&content)?;
+        if !args.wide {
+            if let Some(val) = grid.iter().flatten().flatten().find(|&&val| val > 0xFF) {
+                return Err(format!(
+                    "Cell value {:X} does not fit in one byte; use --wide for larger values",
+                    val
+                ));
+            }
+        }
+        apply_wall_value(grid, args.wall_value)
     } else {
         return Err("Either provide a map file or use --generate".to_string());
     };
-
     if args.animate {
         println!("Searching for minimum cost path...");
-    } else if args.generate.is_some() {
+    } else if args.generate.is_some() && !args.json {
         println!("Finding optimal paths...");
     }
 
-    let (min_path, min_cost, _visited) = dijkstra_min(&grid, args.animate);
+    let opts = search_options(&args, &grid)?;
+    let (start, end) = (opts.start, opts.end);
+
+    if let Some(budget) = args.reachable {
+        let reach = reachable_within(&grid, &opts, budget);
+        let count = reach.iter().flatten().filter(|d| d.is_some()).count();
+        let open = grid.iter().flatten().filter(|c| c.is_some()).count();
+        println!();
+        println!("REACHABLE CELLS");
+        if args.visualize {
+            print_reachable_map(&grid, &reach, &opts);
+        }
+        println!(
+            "\nReachable from ({},{}) within {}: {} of {} open cells",
+            start.1, start.0, budget, count, open
+        );
+        return Ok(());
+    }
+
+    if args.connectivity {
+        let seen = connected_cells(&grid, &opts);
+        let unreachable: Vec<(usize, usize)> = (0..grid.len())
+            .flat_map(|y| (0..grid[0].len()).map(move |x| (y, x)))
+            .filter(|&(y, x)| grid[y][x].is_some() && !seen[y][x])
+            .collect();
+        let open = grid.iter().flatten().filter(|c| c.is_some()).count();
+        println!();
+        println!("CONNECTIVITY");
+        println!(
+            "\nReachable from ({},{}): {} of {} open cells ({} walls)",
+            start.1,
+            start.0,
+            open - unreachable.len(),
+            open,
+            grid.len() * grid[0].len() - open
+        );
+        if unreachable.is_empty() {
+            println!("Every open cell is reachable");
+        } else {
+            let shown: Vec<String> = unreachable
+                .iter()
+                .take(UNREACHABLE_SHOWN)
+                .map(|&(y, x)| format!("({},{})", x, y))
+                .collect();
+            print!("Unreachable: {}", shown.join(", "));
+            if unreachable.len() > UNREACHABLE_SHOWN {
+                print!(" and {} more", unreachable.len() - UNREACHABLE_SHOWN);
+            }
+            println!();
+        }
+        return Ok(());
+    }
+
+    if let Some(k) = args.k_paths {
+        let cap = k_paths_cap(&grid);
+        if k as usize > cap {
+            return Err(format!(
+                "--k-paths {} is too many for a {}x{} grid (at most {})",
+                k,
+                grid[0].len(),
+                grid.len(),
+                cap
+            ));
+        }
+        let paths = k_shortest_paths(&grid, &opts, k as usize);
+        println!();
+        println!("K SHORTEST PATHS");
+        if paths.is_empty() {
+            println!(
+                "\nNo path found from ({},{}) to ({},{})",
+                start.1, start.0, end.1, end.0
+            );
+            return Ok(());
+        }
+        println!();
+        for (i, (path, cost)) in paths.iter().enumerate() {
+            let cells: Vec<String> = path
+                .iter()
+                .map(|&(y, x)| format!("({},{})", x, y))
+                .collect();
+            println!("{}. Cost {}: {}", i + 1, cost, cells.join(" → "));
+        }
+        if paths.len() < k as usize {
+            println!(
+                "\nOnly {} distinct paths exist from ({},{}) to ({},{})",
+                paths.len(),
+                start.1,
+                start.0,
+                end.1,
+                end.0
+            );
+        }
+        return Ok(());
+    }
+
+    let timer = Instant::now();
+    let (min, visited, path_count, dist, timed_out) = dijkstra_min(&grid, &opts);
+    if args.timing {
+        let name = match opts.algorithm {
+            Algorithm::Dijkstra => "Dijkstra",
+            Algorithm::Astar => "A*",
+        };
+        eprintln!(
+            "[timing] min search ({}): {:.3} ms, {} nodes expanded",
+            name,
+            timer.elapsed().as_secs_f64() * 1000.0,
+            visited.len()
+        );
+    }
+    if timed_out {
+        return Err(format!(
+            "Search timed out after {} ms with {} nodes expanded; no path found",
+            args.timeout.unwrap_or(0),
+            visited.len()
+        ));
+    }
+    let max = args.both.then(|| {
+        let timer = Instant::now();
+        let max = max_path_monotone(&grid, &opts);
+        if args.timing {
+            // The DP fills every cell of the start/end rectangle once.
+            let cells = (end.0 + 1).saturating_sub(start.0) * (end.1 + 1).saturating_sub(start.1);
+            eprintln!(
+                "[timing] max search (right/down DP): {:.3} ms, {} cells evaluated",
+                timer.elapsed().as_secs_f64() * 1000.0,
+                cells
+            );
+        }
+        max
+    });
+
+    if let Some(svg_file) = &args.export_svg {
+        let min_path = min.as_ref().map(|(path, _)| path.as_slice());
+        let max_path = max
+            .as_ref()
+            .and_then(|max| max.as_ref())
+            .map(|(path, _)| path.as_slice());
+        fs::write(
+            svg_file,
+            render_svg(&grid, min_path, max_path, opts.hex_grid),
+        )
+        .map_err(|e| format!("Failed to save SVG: {}", e))?;
+        if !args.json {
+            println!("SVG saved to: {}", svg_file);
+        }
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            result_json(&grid, &opts, &min, max.as_ref(), path_count, visited.len())
+        );
+        return Ok(());
+    }
 
     if !args.animate {
         println!();
     }
 
-    println!("MINIMUM COST PATH");
+    if args.heatmap {
+        print_heatmap(&grid, &dist, &opts);
+        println!();
+    }
+
+    let Some((min_path, min_cost)) = min else {
+        println!(
+            "No path found from ({},{}) to ({},{})",
+            start.1, start.0, end.1, end.0
+        );
+        print_expansions(&opts, visited.len());
+        return Ok(());
+    };
+
+    if opts.bottleneck {
+        println!("BOTTLENECK PATH");
+    } else {
+        println!("MINIMUM COST PATH");
+    }
     if args.visualize && !args.both {
-        visualize_map(&grid, &min_path, None);
-        println!("\nCost: {} (minimum)", min_cost);
+        visualize_map(
+            &grid,
+            &min_path,
+            None,
+            opts.color,
+            args.labels,
+            args.arrows,
+            opts.hex_grid,
+        );
+        if opts.bottleneck {
+            println!(
+                "\nCost: {} (bottleneck: highest cell on the path)",
+                min_cost
+            );
+        } else if opts.cost_model == CostModel::Multiply {
+            println!(
+                "\nCost: {} (minimum product)",
+                cost_text(&grid, &opts, &min_path, min_cost)
+            );
+        } else {
+            println!("\nCost: {} (minimum)", min_cost);
+        }
+        if args.stats {
+            print_stats(&grid, &opts, &min_path);
+        }
+        print_path_count(&opts, path_count);
+        print_turns(&opts, &min_path);
+        print_expansions(&opts, visited.len());
     } else if !args.visualize {
-        println!("\nMinimum cost path: {}", min_cost);
+        if opts.bottleneck {
+            println!("\nBottleneck (highest cell on the path): {}", min_cost);
+        } else if opts.cost_model == CostModel::Multiply {
+            println!(
+                "\nMinimum cost path (product of cells): {}",
+                cost_text(&grid, &opts, &min_path, min_cost)
+            );
+        } else {
+            println!("\nMinimum cost path: {}", min_cost);
+        }
+        if args.stats {
+            print_stats(&grid, &opts, &min_path);
+        }
         print!("Path: ");
         for (i, &(y, x)) in min_path.iter().enumerate() {
             if i > 0 {
@@ -485,16 +2022,44 @@ fn main() -> Result<(), String> {
             print!("({},{})", x, y);
         }
         println!();
+        print_path_count(&opts, path_count);
+        print_turns(&opts, &min_path);
+        print_expansions(&opts, visited.len());
     }
 
-    if args.both {
-        let (max_path, max_cost) = dijkstra_max(&grid);
+    if let Some(max) = max {
+        let Some((max_path, max_cost)) = max else {
+            println!(
+                "\nMaximum cost path: none (the end cannot be reached moving only right/down)"
+            );
+            return Ok(());
+        };
         if args.visualize {
-            visualize_map(&grid, &min_path, Some(&max_path));
+            visualize_map(
+                &grid,
+                &min_path,
+                Some(&max_path),
+                opts.color,
+                args.labels,
+                args.arrows,
+                opts.hex_grid,
+            );
             println!("\nCost: {} (minimum)", min_cost);
-            println!("\nCost: {} (maximum)", max_cost);
+            if args.stats {
+                print_stats(&grid, &opts, &min_path);
+            }
+            print_path_count(&opts, path_count);
+            print_turns(&opts, &min_path);
+            print_expansions(&opts, visited.len());
+            println!("\nCost: {} (maximum, right/down moves only)", max_cost);
+            if args.stats {
+                print_stats(&grid, &opts, &max_path);
+            }
         } else {
-            println!("\nMaximum cost path: {}", max_cost);
+            println!("\nMaximum cost path (right/down moves only): {}", max_cost);
+            if args.stats {
+                print_stats(&grid, &opts, &max_path);
+            }
             print!("Path: ");
             for (i, &(y, x)) in max_path.iter().enumerate() {
                 if i > 0 {
@@ -512,3 +2077,394 @@ fn main() -> Result<(), String> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(grid: &[Vec<Cell>]) -> SearchOptions {
+        SearchOptions {
+            algorithm: Algorithm::Dijkstra,
+            heuristic_weight: 1.0,
+            animate: false,
+            animate_delay: Duration::ZERO,
+            animate_every: 10,
+            color: false,
+            diagonals: false,
+            hex_grid: false,
+            start: (0, 0),
+            end: (grid.len() - 1, grid[0].len() - 1),
+            count_paths: false,
+            bottleneck: false,
+            exclude_start: false,
+            cost_model: CostModel::Add,
+            turn_penalty: 0,
+            timeout: None,
+        }
+    }
+
+    #[test]
+    fn walled_off_end_has_no_path() {
+        let grid = parse_map("00 05 01\n03 XX XX\n04 XX FF\n").unwrap();
+        let opts = options(&grid);
+
+        let (min, visited, _, _, _) = dijkstra_min(&grid, &opts);
+        assert!(min.is_none());
+        assert_eq!(visited.len(), 5);
+        assert!(max_path_monotone(&grid, &opts).is_none());
+
+        let opts = SearchOptions {
+            algorithm: Algorithm::Astar,
+            ..opts
+        };
+        assert!(dijkstra_min(&grid, &opts).0.is_none());
+    }
+
+    #[test]
+    fn max_path_is_exact_for_right_down_moves() {
+        // The old greedy search finalized (1,1) at cost 4 and reached the end
+        // at 6, never taking the 09 on the top row.
+        let grid = parse_map("00 01 09\n02 02 02\n").unwrap();
+        let (path, cost) = max_path_monotone(&grid, &options(&grid)).unwrap();
+        assert_eq!(cost, 12);
+        assert_eq!(path, vec![(0, 0), (0, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn counts_every_minimum_cost_path() {
+        // Uniform 3x3 grid: every right/down route costs 4, and there are
+        // C(4, 2) = 6 of them.
+        let grid = parse_map("01 01 01\n01 01 01\n01 01 01\n").unwrap();
+        for algorithm in [Algorithm::Dijkstra, Algorithm::Astar] {
+            let opts = SearchOptions {
+                algorithm,
+                count_paths: true,
+                ..options(&grid)
+            };
+            let (min, _, count, _, _) = dijkstra_min(&grid, &opts);
+            assert_eq!(min.unwrap().1, 5);
+            assert_eq!(count, 6);
+        }
+    }
+
+    #[test]
+    fn json_uses_x_y_coordinates() {
+        let grid = parse_map("00 01 09\n02 02 02\n").unwrap();
+        let opts = options(&grid);
+        let (min, visited, count, _, _) = dijkstra_min(&grid, &opts);
+        assert_eq!(
+            result_json(&grid, &opts, &min, Some(&None), count, visited.len()),
+            "{\"width\":3,\"height\":2,\"start\":[0,0],\"end\":[2,1],\
+             \"min\":{\"cost\":5,\"path\":[[0,0],[1,0],[1,1],[2,1]]},\
+             \"objective\":\"sum\",\"optimal\":true,\"nodes_expanded\":5,\"max\":null}"
+        );
+    }
+
+    #[test]
+    fn bottleneck_avoids_the_worst_cell() {
+        // The cheapest sum goes through the 80 (0+80+1 = 81); the bottleneck
+        // route detours over 30s to keep its highest cell at 30.
+        let grid = parse_map("00 80 01\n30 XX 30\n30 30 30\n").unwrap();
+        let opts = SearchOptions {
+            end: (0, 2),
+            ..options(&grid)
+        };
+        let (min, _, _, _, _) = dijkstra_min(&grid, &opts);
+        assert_eq!(min.unwrap().1, 0x81);
+
+        let opts = SearchOptions {
+            bottleneck: true,
+            ..opts
+        };
+        let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+        assert_eq!(cost, 0x30);
+        assert!(!path.contains(&(0, 1)));
+    }
+
+    #[test]
+    fn reachable_stops_at_the_budget() {
+        let grid = parse_map("01 02 03\n04 XX 05\n06 07 08\n").unwrap();
+        let reach = reachable_within(&grid, &options(&grid), 6);
+        let reached: Vec<Option<u64>> = reach.into_iter().flatten().collect();
+        assert_eq!(
+            reached,
+            vec![
+                Some(1),
+                Some(3),
+                Some(6),
+                Some(5),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn multiply_minimizes_the_product() {
+        // Dropping down at column 1 gives the cheapest sum (1+1+4+4+1 = 11,
+        // product 16); staying on top through the 0C gives the cheapest
+        // product (12, sum 16).
+        let grid = parse_map("01 01 0C 01\n02 04 04 01\n").unwrap();
+        let opts = options(&grid);
+        let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+        assert_eq!(cost, 11);
+        assert_eq!(path, vec![(0, 0), (0, 1), (1, 1), (1, 2), (1, 3)]);
+
+        let opts = SearchOptions {
+            cost_model: CostModel::Multiply,
+            ..opts
+        };
+        let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+        assert_eq!(cost_text(&grid, &opts, &path, cost), "12");
+        assert_eq!(path, vec![(0, 0), (0, 1), (0, 2), (0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn turn_penalty_prefers_straight_paths() {
+        // Every route to the far corner costs 6 without penalties; with one,
+        // the two single-turn routes along the edges win.
+        let grid = parse_map("00 01 01 01\n01 01 01 01\n01 01 01 01\n01 01 01 01\n").unwrap();
+        let opts = SearchOptions {
+            turn_penalty: 5,
+            ..options(&grid)
+        };
+        let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+        assert_eq!(cost, 6 + 5);
+        assert_eq!(count_turns(&path, false), 1);
+    }
+
+    #[test]
+    fn hex_grid_uses_six_neighbors_by_row_parity() {
+        let grid = parse_map("00 01 09\n09 01 09\n09 09 01\n").unwrap();
+        let opts = SearchOptions {
+            hex_grid: true,
+            ..options(&grid)
+        };
+        let cells = |pos| {
+            let mut cells: Vec<_> = neighbors(&grid, pos, &opts)
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect();
+            cells.sort();
+            cells
+        };
+        // Even rows touch the row above and below on their left, odd rows on
+        // their right; steps off the grid are dropped.
+        assert_eq!(cells((0, 0)), vec![(0, 1), (1, 0)]);
+        assert_eq!(
+            cells((1, 1)),
+            vec![(0, 1), (0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]
+        );
+        assert_eq!(cells((2, 2)), vec![(1, 1), (1, 2), (2, 1)]);
+
+        // The square grid has to pay a 09 to reach the corner.
+        assert_eq!(dijkstra_min(&grid, &options(&grid)).0.unwrap().1, 12);
+        for algorithm in [Algorithm::Dijkstra, Algorithm::Astar] {
+            let opts = SearchOptions { algorithm, ..opts };
+            let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+            assert_eq!(cost, 3);
+            assert_eq!(path, vec![(0, 0), (0, 1), (1, 1), (2, 2)]);
+        }
+    }
+
+    #[test]
+    fn k_paths_are_distinct_and_ascending() {
+        // The two routes around the 09 cost 4; every route through it at
+        // least 12.
+        let grid = parse_map("00 01 01\n01 09 01\n01 01 01\n").unwrap();
+        let opts = options(&grid);
+        let paths = k_shortest_paths(&grid, &opts, 10);
+        let costs: Vec<u64> = paths.iter().map(|(_, cost)| *cost).collect();
+        assert_eq!(costs[..3], [4, 4, 12]);
+        assert!(costs.windows(2).all(|w| w[0] <= w[1]));
+        let distinct: HashSet<_> = paths.iter().map(|(path, _)| path).collect();
+        assert_eq!(distinct.len(), paths.len());
+        for (path, cost) in &paths {
+            let sum: u64 = path
+                .iter()
+                .filter_map(|&(y, x)| grid[y][x])
+                .map(u64::from)
+                .sum();
+            assert_eq!(sum, *cost);
+        }
+    }
+
+    #[test]
+    fn malformed_maps_report_the_line() {
+        let err = parse_map("00 01\n02 G3\n").unwrap_err();
+        assert!(
+            err.starts_with("Line 2, column 2: invalid cell 'G3'"),
+            "{}",
+            err
+        );
+        // Blank lines still count towards the line number.
+        let err = parse_map("00 01\n\n02\n").unwrap_err();
+        assert_eq!(err, "Line 3: 1 cells, but the first row has 2");
+        assert_eq!(parse_map(" \n\n").unwrap_err(), "Empty map");
+        assert!(parse_map("00 xx\n01 FF\n").is_ok());
+    }
+
+    #[test]
+    fn endpoints_must_be_open_cells_inside_the_grid() {
+        let grid = parse_map("00 XX\n01 02\n").unwrap();
+        assert!(validate_coord(&grid, (1, 1), "End").is_ok());
+        assert_eq!(
+            validate_coord(&grid, (0, 1), "Start").unwrap_err(),
+            "Start (1,0) is a wall"
+        );
+        assert!(validate_coord(&grid, (2, 0), "End")
+            .unwrap_err()
+            .starts_with("End (0,2) is outside the 2x2 grid"));
+    }
+
+    #[test]
+    fn search_gives_up_after_the_timeout() {
+        let grid = vec![vec![Some(1); 40]; 40];
+        let opts = SearchOptions {
+            timeout: Some(Duration::ZERO),
+            ..options(&grid)
+        };
+        let (min, visited, _, _, timed_out) = dijkstra_min(&grid, &opts);
+        assert!(timed_out);
+        assert!(min.is_none());
+        assert_eq!(visited.len(), TIMEOUT_CHECK_EVERY);
+        assert!(!dijkstra_min(&grid, &options(&grid)).4);
+    }
+
+    #[test]
+    fn arrows_follow_the_direction_of_travel() {
+        assert_eq!(arrow((1, 1), (1, 2), false), '→');
+        assert_eq!(arrow((1, 1), (0, 1), false), '↑');
+        assert_eq!(arrow((1, 1), (2, 0), false), '↙');
+        // Odd rows are drawn half a cell right, so straight down in the
+        // grid leans right from an even row and left from an odd one.
+        assert_eq!(arrow((0, 1), (1, 1), true), '↘');
+        assert_eq!(arrow((0, 1), (1, 0), true), '↙');
+        assert_eq!(arrow((1, 1), (2, 1), true), '↙');
+        assert_eq!(arrow((1, 1), (0, 2), true), '↗');
+    }
+
+    #[test]
+    fn csv_maps_round_trip() {
+        let grid = parse_map("00 1F XX\nA0 05 FF\n").unwrap();
+        let csv = map_text(&grid, MapFormat::Csv);
+        assert_eq!(csv, "00,1F,XX\nA0,05,FF\n");
+        assert_eq!(parse_map(&csv).unwrap(), grid);
+        assert_eq!(parse_map("00, 1F ,xx\nA0,05,FF\n").unwrap(), grid);
+        let err = parse_map("00,01\n02,,03\n").unwrap_err();
+        assert!(
+            err.starts_with("Line 2, column 2: invalid cell ''"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn connectivity_follows_the_adjacency() {
+        // The bottom right cell is only touched diagonally.
+        let grid = parse_map("00 01 XX\n01 XX 02\nXX 03 XX\n").unwrap();
+        let opts = options(&grid);
+        let seen = connected_cells(&grid, &opts);
+        assert_eq!(seen[0], [true, true, false]);
+        assert!(!seen[1][2] && !seen[2][1]);
+
+        let opts = SearchOptions {
+            diagonals: true,
+            ..opts
+        };
+        let seen = connected_cells(&grid, &opts);
+        assert!(seen[1][2] && seen[2][1]);
+    }
+
+    #[test]
+    fn exclude_start_drops_the_start_cell_from_both_searches() {
+        let grid = parse_map("05 01 09\n02 02 02\n").unwrap();
+        let opts = options(&grid);
+        let min = dijkstra_min(&grid, &opts).0.unwrap();
+        let max = max_path_monotone(&grid, &opts).unwrap();
+        assert_eq!((min.1, max.1), (5 + 1 + 2 + 2, 5 + 1 + 9 + 2));
+
+        let opts = SearchOptions {
+            exclude_start: true,
+            ..opts
+        };
+        let excluded_min = dijkstra_min(&grid, &opts).0.unwrap();
+        let excluded_max = max_path_monotone(&grid, &opts).unwrap();
+        assert_eq!(excluded_min, (min.0, min.1 - 5));
+        assert_eq!(excluded_max, (max.0, max.1 - 5));
+        assert_eq!(k_shortest_paths(&grid, &opts, 2)[1].1, 2 + 2 + 2);
+    }
+
+    #[test]
+    fn wide_costs_do_not_overflow() {
+        // Relaxation steps back into the start, paying its value twice.
+        let grid = parse_map("FFFFFF00 01\nFFFFFFFF 01\n").unwrap();
+        let opts = SearchOptions {
+            turn_penalty: u32::MAX,
+            ..options(&grid)
+        };
+        let (path, cost) = dijkstra_min(&grid, &opts).0.unwrap();
+        assert_eq!(path, vec![(0, 0), (0, 1), (1, 1)]);
+        assert_eq!(cost, 0xFFFF_FF00 + 1 + 1 + u64::from(u32::MAX));
+        let (_, cost) = max_path_monotone(&grid, &opts).unwrap();
+        assert_eq!(cost, 0xFFFF_FF00 + 0xFFFF_FFFF + 1);
+    }
+
+    #[test]
+    fn huge_heuristic_weight_saturates() {
+        let grid = parse_map("00 01 09\n02 02 02\n").unwrap();
+        for heuristic_weight in [1e12, 1e30] {
+            let opts = SearchOptions {
+                algorithm: Algorithm::Astar,
+                heuristic_weight,
+                ..options(&grid)
+            };
+            let (path, _) = dijkstra_min(&grid, &opts).0.unwrap();
+            assert_eq!(path.first(), Some(&(0, 0)));
+            assert_eq!(path.last(), Some(&(1, 2)));
+        }
+        assert!(parse_weight("-1")
+            .unwrap_err()
+            .starts_with("Invalid weight"));
+    }
+
+    #[test]
+    fn wide_colors_spread_over_the_observed_range() {
+        let grid = parse_map("1000 1040\n1080 1100\n").unwrap();
+        let scale = color_scale(&grid);
+        assert_eq!(scale, (0x1000, 0x1100));
+        assert_eq!(color_level(0x1000, scale), 0x00);
+        assert_eq!(color_level(0x1100, scale), 0xFF);
+        assert_ne!(
+            get_color_code(color_level(0x1040, scale)),
+            get_color_code(color_level(0x1080, scale))
+        );
+        // One-byte maps keep the fixed 00-FF gradient.
+        assert_eq!(color_scale(&parse_map("10 20\n").unwrap()), BYTE_SCALE);
+    }
+
+    #[test]
+    fn generated_maps_need_cells() {
+        for size in ["5x0", "0x5", "0x0"] {
+            assert_eq!(
+                generate_map(size.to_string(), 1, None).unwrap_err(),
+                "Width and height must be at least 1"
+            );
+        }
+        assert_eq!(generate_map("1x1".to_string(), 1, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn zero_cells_make_the_path_count_a_lower_bound() {
+        let grid = parse_map("00 00 00\n00 00 00\n00 00 00\n").unwrap();
+        let opts = SearchOptions {
+            count_paths: true,
+            ..options(&grid)
+        };
+        let (_, _, count, _, _) = dijkstra_min(&grid, &opts);
+        assert!((1..=12).contains(&count), "{}", count);
+    }
+}